@@ -0,0 +1,92 @@
+//! Frame times of the `Streaming` strategies for a UI whose geometry changes every frame.
+//!
+//! Run with `cargo bench --features testing`. Needs an EGL capable GL driver and a TrueType font,
+//! taken from `NUKLEAR_BENCH_FONT` or DejaVu Sans at its usual Linux location.
+
+use std::time::{Duration, Instant};
+
+use glium::Surface;
+use nuklear::{nk_string, Allocator, Buffer, Context, Flags, PanelFlags, Rect, TextAlignment, Vec2};
+use nuklear_backend_glium::testing::context;
+use nuklear_backend_glium::{Drawer, FontManager, Streaming};
+
+const WIDTH: u32 = 1280;
+const HEIGHT: u32 = 720;
+const WARMUP: usize = 50;
+const FRAMES: usize = 500;
+
+fn main() {
+    let path = std::env::var("NUKLEAR_BENCH_FONT").unwrap_or_else(|_| "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".into());
+    let ttf = match std::fs::read(&path) {
+        Ok(ttf) => ttf,
+        Err(e) => {
+            eprintln!("skipping, cannot read font {}: {}", path, e);
+            return;
+        }
+    };
+    let facade = match context(WIDTH, HEIGHT) {
+        Ok(facade) => facade,
+        Err(e) => {
+            eprintln!("skipping, no headless GL context: {}", e);
+            return;
+        }
+    };
+    println!("{}", facade.get_opengl_renderer_string());
+
+    for &streaming in &[Streaming::Orphan, Streaming::Ring(3), Streaming::Persistent(3)] {
+        let mut allocator = Allocator::new_vec();
+        let mut drawer = Drawer::new(&facade, 4, 4096, 4096, Buffer::with_size(&mut allocator, 64 * 1024));
+        drawer.set_streaming(streaming);
+        let mut fonts = FontManager::new(&allocator);
+        let font = fonts.add_font(&ttf, 14.0);
+        let mut cfg = fonts.bake(&mut drawer, &facade, 1.0).unwrap();
+        let mut ctx = Context::new(&mut allocator, fonts.font(font).unwrap().handle());
+        let target = glium::Texture2d::empty(&facade, WIDTH, HEIGHT).unwrap();
+        let mut fb = glium::framebuffer::SimpleFrameBuffer::new(&facade, &target).unwrap();
+
+        let mut elapsed = Duration::default();
+        for frame in 0..WARMUP + FRAMES {
+            let start = Instant::now();
+            ctx.input_begin();
+            ctx.input_end();
+            ui(&mut ctx, frame);
+            fb.clear_color(0.0, 0.0, 0.0, 1.0);
+            drawer.draw(&mut ctx, &mut cfg, &mut fb, Vec2 { x: 1.0, y: 1.0 });
+            ctx.clear();
+            if frame >= WARMUP {
+                elapsed += start.elapsed();
+            }
+        }
+        let start = Instant::now();
+        facade.finish();
+        elapsed += start.elapsed();
+
+        let stats = drawer.stats();
+        println!(
+            "{:<16} {:>9.3} ms/frame  ({} vertices, {} indices, {} draw calls)",
+            format!("{:?}", streaming),
+            elapsed.as_secs_f64() * 1000.0 / FRAMES as f64,
+            stats.vertices,
+            stats.indices,
+            stats.draw_calls
+        );
+    }
+}
+
+// A few windows full of widgets, with a frame counter so every frame is converted and uploaded again.
+fn ui(ctx: &mut Context, frame: usize) {
+    for window in 0..4 {
+        let x = 10.0 + window as f32 * 315.0;
+        if ctx.begin(nk_string!("Window {}", window), Rect { x, y: 10.0, w: 305.0, h: 700.0 }, PanelFlags::Border as Flags | PanelFlags::Title as Flags) {
+            ctx.layout_row_dynamic(20.0, 1);
+            ctx.text(&format!("Frame {}", frame), TextAlignment::Left as Flags);
+            for row in 0..25 {
+                ctx.layout_row_dynamic(22.0, 3);
+                ctx.button_text(&format!("Button {}", row));
+                ctx.text(&format!("{}", (frame + row) % 97), TextAlignment::Centered as Flags);
+                ctx.progress(&mut ((frame + row * 7) % 100), 100, false);
+            }
+        }
+        ctx.end();
+    }
+}
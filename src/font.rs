@@ -0,0 +1,113 @@
+use glium::backend::Facade;
+
+use nuklear::{Allocator, AntiAliasing, ConvertConfig, DrawNullTexture, Font, FontAtlas, FontAtlasFormat, FontConfig, FontID, Handle};
+
+use crate::{Drawer, DrawerError, ImageFormat, TextureOptions};
+
+/// Owns the TrueType sources, the baked nuklear `FontAtlas` and its texture in a `Drawer`.
+///
+/// Fonts are baked at `size * scale` pixels but keep reporting `size` as their height, so layouts stay in
+/// the logical pixels that `Drawer::draw` scales, while glyphs stay sharp on HiDPI surfaces.
+///
+/// Baking again frees the previous fonts: pass the new `font(..).handle()` to `Context::style_set_font`
+/// before the next frame, and drop every `Context` using these fonts before the manager.
+pub struct FontManager {
+    sources: Vec<(Vec<u8>, f32)>,
+    ids: Vec<FontID>,
+    atlas: Option<FontAtlas>,
+    texture: Option<Handle>,
+    allocator: Allocator,
+    scale: f32,
+}
+
+impl FontManager {
+    pub fn new(allocator: &Allocator) -> FontManager {
+        FontManager {
+            sources: Vec::new(),
+            ids: Vec::new(),
+            atlas: None,
+            texture: None,
+            allocator: allocator.clone(),
+            scale: 1.0,
+        }
+    }
+
+    /// Queues a TrueType font for the next `bake` and returns its index for `font`.
+    pub fn add_font(&mut self, ttf: &[u8], size: f32) -> usize {
+        self.sources.push((ttf.to_vec(), size));
+        self.sources.len() - 1
+    }
+
+    /// Bakes all fonts for a surface with `scale` pixels per logical pixel and uploads the atlas into `drawer`.
+    ///
+    /// The first bake registers the atlas texture, later ones replace its image under the same handle.
+    /// The returned config has its null texture pointing at the atlas' white pixel.
+    pub fn bake<F: Facade + ?Sized>(&mut self, drawer: &mut Drawer, facade: &F, scale: f32) -> Result<ConvertConfig, DrawerError> {
+        let mut atlas = FontAtlas::new(&mut self.allocator);
+        let mut ids = Vec::with_capacity(self.sources.len());
+        for (index, &(ref ttf, size)) in self.sources.iter().enumerate() {
+            // Leaves `ttf_data_owned_by_atlas` unset, so nuklear copies the data instead of freeing ours.
+            let mut cfg = FontConfig::with_size(size * scale);
+            cfg.set_ttf(ttf);
+            ids.push(atlas.add_font_with_config(&cfg).ok_or(DrawerError::Font(index))?);
+        }
+
+        let options = TextureOptions {
+            format: ImageFormat::Alpha,
+            ..TextureOptions::default()
+        };
+        let handle = {
+            let (image, width, height) = atlas.bake(FontAtlasFormat::Alpha8);
+            match self.texture {
+                Some(handle) => match drawer.replace_texture(facade, handle, image, width, height) {
+                    Ok(()) => handle,
+                    // The old atlas was removed from the drawer, or these fonts are baked for another one.
+                    Err(DrawerError::MissingTexture(_)) => drawer.try_add_texture_with(facade, image, width, height, options)?,
+                    Err(e) => return Err(e),
+                },
+                None => drawer.try_add_texture_with(facade, image, width, height, options)?,
+            }
+        };
+        let mut null = DrawNullTexture::default();
+        atlas.end(handle, Some(&mut null));
+
+        unsafe {
+            let mut font = atlas.as_mut().fonts;
+            while !font.is_null() {
+                (*font).handle.height /= scale;
+                font = (*font).next;
+            }
+        }
+
+        self.atlas = Some(atlas);
+        self.ids = ids;
+        self.texture = Some(handle);
+        self.scale = scale;
+
+        let mut cfg = ConvertConfig::default();
+        cfg.set_null(null);
+        cfg.set_circle_segment_count(22);
+        cfg.set_curve_segment_count(22);
+        cfg.set_arc_segment_count(22);
+        cfg.set_global_alpha(1.0);
+        cfg.set_shape_aa(AntiAliasing::On);
+        cfg.set_line_aa(AntiAliasing::On);
+        Ok(cfg)
+    }
+
+    /// The font added as `index`, once baked.
+    pub fn font(&self, index: usize) -> Option<&Font> {
+        let id = *self.ids.get(index)?;
+        self.atlas.as_ref().and_then(|atlas| atlas.font(id))
+    }
+
+    /// Handle of the atlas texture in the drawer, once baked.
+    pub fn texture(&self) -> Option<Handle> {
+        self.texture
+    }
+
+    /// Scale of the last bake.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+}
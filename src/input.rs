@@ -0,0 +1,223 @@
+use std::time::{Duration, Instant};
+
+use glium::glutin::event::{ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use nuklear::{Button, Context, Key, Vec2};
+
+/// Turns winit window events into `Context::input_*` calls.
+///
+/// Feed every event of a frame to `handle` (or `handle_event`) between `Context::input_begin` and `Context::input_end`.
+/// Window coordinates are divided by the same scale that is passed to `Drawer::draw`, so nuklear sees logical pixels.
+pub struct InputTranslator {
+    scale: Vec2,
+    cursor: (i32, i32),
+    modifiers: ModifiersState,
+    keys: Vec<(VirtualKeyCode, Key)>,
+    buttons: Vec<Button>,
+    last_click: Option<Instant>,
+    double_click: Duration,
+    pixels_per_line: f32,
+}
+
+impl Default for InputTranslator {
+    fn default() -> Self {
+        InputTranslator::new(Vec2 { x: 1.0, y: 1.0 })
+    }
+}
+
+impl InputTranslator {
+    pub fn new(scale: Vec2) -> InputTranslator {
+        InputTranslator {
+            scale: scale,
+            cursor: (0, 0),
+            modifiers: ModifiersState::empty(),
+            keys: Vec::new(),
+            buttons: Vec::new(),
+            last_click: None,
+            double_click: Duration::from_millis(250),
+            pixels_per_line: 20.0,
+        }
+    }
+
+    pub fn scale(&self) -> Vec2 {
+        self.scale
+    }
+
+    /// Updated automatically on `WindowEvent::ScaleFactorChanged`.
+    pub fn set_scale(&mut self, scale: Vec2) {
+        self.scale = scale;
+    }
+
+    /// Longest interval between two left clicks that still reports `Button::Double`.
+    pub fn set_double_click_interval(&mut self, interval: Duration) {
+        self.double_click = interval;
+    }
+
+    /// How many pixels of a touchpad `PixelDelta` scroll make one nuklear scroll line.
+    pub fn set_pixels_per_line(&mut self, pixels: f32) {
+        self.pixels_per_line = pixels;
+    }
+
+    /// Last cursor position, in logical pixels.
+    pub fn cursor(&self) -> (i32, i32) {
+        self.cursor
+    }
+
+    pub fn handle_event<T>(&mut self, ctx: &mut Context, event: &Event<T>) -> bool {
+        match *event {
+            Event::WindowEvent { ref event, .. } => self.handle(ctx, event),
+            _ => false,
+        }
+    }
+
+    /// Forwards `event` to nuklear and returns whether nuklear wants to capture it,
+    /// that is the pointer is over a nuklear window or a widget is active (for keyboard and text, only the latter).
+    ///
+    /// The answer is based on the UI state of the previous frame.
+    pub fn handle(&mut self, ctx: &mut Context, event: &WindowEvent) -> bool {
+        match *event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = ((position.x as f32 / self.scale.x) as i32, (position.y as f32 / self.scale.y) as i32);
+                ctx.input_motion(self.cursor.0, self.cursor.1);
+                wants_pointer(ctx)
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => Button::Left,
+                    MouseButton::Middle => Button::Middle,
+                    MouseButton::Right => Button::Right,
+                    MouseButton::Other(_) => return false,
+                };
+                let down = state == ElementState::Pressed;
+                if button == Button::Left {
+                    let double = down && self.double_clicked();
+                    ctx.input_button(Button::Double, self.cursor.0, self.cursor.1, double);
+                }
+                self.button(ctx, button, down);
+                wants_pointer(ctx)
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => Vec2 { x: x, y: y },
+                    MouseScrollDelta::PixelDelta(p) => Vec2 {
+                        x: p.x as f32 / self.scale.x / self.pixels_per_line,
+                        y: p.y as f32 / self.scale.y / self.pixels_per_line,
+                    },
+                };
+                ctx.input_scroll(delta);
+                wants_pointer(ctx)
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                ctx.input_key(Key::Shift, modifiers.shift());
+                ctx.input_key(Key::Ctrl, self.command());
+                false
+            }
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { virtual_keycode: Some(code), state, .. },
+                ..
+            } => {
+                if state == ElementState::Pressed {
+                    for key in translate_key(code, self.command()) {
+                        if !self.keys.contains(&(code, *key)) {
+                            self.keys.push((code, *key));
+                        }
+                        ctx.input_key(*key, true);
+                    }
+                } else {
+                    // Release what the press mapped to, even if the modifiers changed in between.
+                    self.keys.retain(|&(held, key)| {
+                        if held == code {
+                            ctx.input_key(key, false);
+                        }
+                        held != code
+                    });
+                }
+                ctx.item_is_any_active()
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                if c.is_control() || self.command() {
+                    return false;
+                }
+                ctx.input_unicode(c);
+                ctx.item_is_any_active()
+            }
+            WindowEvent::Focused(false) => {
+                for (_, key) in self.keys.drain(..) {
+                    ctx.input_key(key, false);
+                }
+                for button in std::mem::take(&mut self.buttons) {
+                    ctx.input_button(button, self.cursor.0, self.cursor.1, false);
+                }
+                ctx.input_key(Key::Shift, false);
+                ctx.input_key(Key::Ctrl, false);
+                self.modifiers = ModifiersState::empty();
+                false
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale = Vec2 {
+                    x: scale_factor as f32,
+                    y: scale_factor as f32,
+                };
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn button(&mut self, ctx: &mut Context, button: Button, down: bool) {
+        self.buttons.retain(|&b| b != button);
+        if down {
+            self.buttons.push(button);
+        }
+        ctx.input_button(button, self.cursor.0, self.cursor.1, down);
+    }
+
+    fn double_clicked(&mut self) -> bool {
+        let now = Instant::now();
+        let double = self.last_click.is_some_and(|last| now.duration_since(last) <= self.double_click);
+        self.last_click = if double { None } else { Some(now) };
+        double
+    }
+
+    // The shortcut modifier: Cmd on macOS, Ctrl elsewhere.
+    fn command(&self) -> bool {
+        if cfg!(target_os = "macos") {
+            self.modifiers.logo()
+        } else {
+            self.modifiers.ctrl()
+        }
+    }
+}
+
+fn wants_pointer(ctx: &Context) -> bool {
+    ctx.window_is_any_hovered() || ctx.item_is_any_active()
+}
+
+fn translate_key(code: VirtualKeyCode, command: bool) -> &'static [Key] {
+    match code {
+        VirtualKeyCode::Delete => &[Key::Del],
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => &[Key::Enter],
+        VirtualKeyCode::Tab => &[Key::Tab],
+        VirtualKeyCode::Back => &[Key::Backspace],
+        VirtualKeyCode::Up => &[Key::Up],
+        VirtualKeyCode::Down => &[Key::Down],
+        VirtualKeyCode::Left if command => &[Key::TextWordLeft],
+        VirtualKeyCode::Right if command => &[Key::TextWordRight],
+        VirtualKeyCode::Left => &[Key::Left],
+        VirtualKeyCode::Right => &[Key::Right],
+        VirtualKeyCode::Home => &[Key::TextStart, Key::ScrollStart],
+        VirtualKeyCode::End => &[Key::TextEnd, Key::ScrollEnd],
+        VirtualKeyCode::PageUp => &[Key::ScrollUp],
+        VirtualKeyCode::PageDown => &[Key::ScrollDown],
+        VirtualKeyCode::C if command => &[Key::Copy],
+        VirtualKeyCode::X if command => &[Key::Cut],
+        VirtualKeyCode::V if command => &[Key::Paste],
+        VirtualKeyCode::Z if command => &[Key::TextUndo],
+        VirtualKeyCode::Y | VirtualKeyCode::R if command => &[Key::TextRedo],
+        VirtualKeyCode::A if command => &[Key::TextSelectAll],
+        VirtualKeyCode::B if command => &[Key::LineStart],
+        VirtualKeyCode::E if command => &[Key::LineEnd],
+        _ => &[],
+    }
+}
@@ -1,9 +1,34 @@
-#![cfg_attr(feature = "cargo-clippy", allow(redundant_field_names))] // for clarity
+#![allow(clippy::redundant_field_names)] // for clarity
 
-#[macro_use]
 pub extern crate glium;
+#[macro_use]
+extern crate log;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::Duration;
+
+use glium::backend::Facade;
+use glium::uniforms::{UniformValue, Uniforms};
+
+use nuklear::{Buffer, CommandType, Context, ConvertConfig, DrawVertexLayoutAttribute, DrawVertexLayoutElements, DrawVertexLayoutFormat, Handle, Vec2};
 
-use nuklear::{Buffer, Context, ConvertConfig, DrawVertexLayoutAttribute, DrawVertexLayoutElements, DrawVertexLayoutFormat, Handle, Vec2};
+mod font;
+mod input;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod texture;
+
+pub use font::FontManager;
+pub use input::InputTranslator;
+pub use texture::{BlendMode, FrameTextures, ImageFormat, TextureOptions};
+
+use texture::{find_res, find_slot, handle_id, resolve, slot_id, Texture, TextureRef, TextureSlot, MAX_SLOTS};
 
 #[derive(Debug, Copy, Clone)]
 struct Vertex {
@@ -14,17 +39,11 @@ struct Vertex {
 
 impl glium::vertex::Vertex for Vertex {
     fn build_bindings() -> glium::vertex::VertexFormat {
-        use std::mem::transmute;
-
-        #[allow(invalid_value)]
-        unsafe {
-            let dummy: &Vertex = ::std::mem::transmute(0usize);
-            ::std::borrow::Cow::Owned(vec![
-                ("Position".into(), transmute(&dummy.pos), <(f32, f32) as glium::vertex::Attribute>::get_type(), false),
-                ("TexCoord".into(), transmute(&dummy.tex), <(f32, f32) as glium::vertex::Attribute>::get_type(), false),
-                ("Color".into(), transmute(&dummy.col), glium::vertex::AttributeType::U8U8U8U8, false),
-            ])
-        }
+        ::std::borrow::Cow::Owned(vec![
+            ("Position".into(), ::std::mem::offset_of!(Vertex, pos), <(f32, f32) as glium::vertex::Attribute>::get_type(), false),
+            ("TexCoord".into(), ::std::mem::offset_of!(Vertex, tex), <(f32, f32) as glium::vertex::Attribute>::get_type(), false),
+            ("Color".into(), ::std::mem::offset_of!(Vertex, col), glium::vertex::AttributeType::U8U8U8U8, false),
+        ])
     }
 }
 
@@ -36,153 +55,1194 @@ impl Default for Vertex {
 
 const VS: &str = "#version 150
         uniform mat4 ProjMtx;
+        uniform bool LinearColors;
         in vec2 Position;
         in vec2 TexCoord;
         in vec4 Color;
         out vec2 Frag_UV;
         out vec4 Frag_Color;
+        out vec2 Frag_Pos;
+        vec3 to_linear(vec3 c) {
+           return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+        }
         void main() {
            Frag_UV = \
                           TexCoord;
            Frag_Color = Color / 255.0;
+           if (LinearColors) {
+              Frag_Color.rgb = to_linear(Frag_Color.rgb);
+           }
+           Frag_Pos = Position;
            gl_Position = ProjMtx * vec4(Position.xy, 0, 1);
         }";
 const FS: &str = "#version 150
         precision mediump float;
 	    uniform sampler2D Texture;
+        uniform int Swizzle;
+        uniform bool Premultiply;
+        uniform int TexelConvert;
+        uniform bool EncodeOutput;
+        uniform bool ClipEnabled;
+        uniform vec4 ClipRect;
         in vec2 Frag_UV;
         in vec4 Frag_Color;
+        in vec2 Frag_Pos;
         out vec4 Out_Color;
+        vec3 to_linear(vec3 c) {
+           return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+        }
+        vec3 to_srgb(vec3 c) {
+           return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+        }
         void main(){
-           Out_Color = Frag_Color * \
-                          texture(Texture, Frag_UV.st);
+           if (ClipEnabled && (any(lessThan(Frag_Pos, ClipRect.xy)) || any(greaterThanEqual(Frag_Pos, ClipRect.zw)))) {
+              discard;
+           }
+           vec4 texel = texture(Texture, Frag_UV.st);
+           if (Swizzle == 1) {
+              texel = vec4(1.0, 1.0, 1.0, texel.r);
+           } else if (Swizzle == 2) {
+              texel = vec4(texel.rrr, 1.0);
+           } else if (Swizzle == 3) {
+              texel = texel.bgra;
+           }
+           if (TexelConvert == 1) {
+              texel.rgb = to_linear(texel.rgb);
+           } else if (TexelConvert == 2) {
+              texel.rgb = to_srgb(texel.rgb);
+           }
+           Out_Color = Frag_Color * texel;
+           if (EncodeOutput) {
+              Out_Color.rgb = to_srgb(Out_Color.rgb);
+           }
+           if (Premultiply) {
+              Out_Color.rgb *= Out_Color.a;
+           }
 		}";
 
+#[derive(Debug)]
+pub enum DrawerError {
+    Program(glium::ProgramCreationError),
+    Texture(glium::texture::TextureCreationError),
+    VertexBuffer(glium::vertex::BufferCreationError),
+    IndexBuffer(glium::index::BufferCreationError),
+    Draw(glium::DrawError),
+    MissingTexture(i32),
+    RegionOutOfBounds,
+    ImageSize { expected: usize, actual: usize },
+    Font(usize),
+    TextureSlots,
+}
+
+impl fmt::Display for DrawerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DrawerError::Program(ref e) => write!(f, "Shader program creation failed: {}", e),
+            DrawerError::Texture(ref e) => write!(f, "Texture creation failed: {}", e),
+            DrawerError::VertexBuffer(ref e) => write!(f, "Vertex buffer creation failed: {}", e),
+            DrawerError::IndexBuffer(ref e) => write!(f, "Index buffer creation failed: {}", e),
+            DrawerError::Draw(ref e) => write!(f, "Draw call failed: {}", e),
+            DrawerError::MissingTexture(id) => write!(f, "No texture registered for handle id {}", id),
+            DrawerError::RegionOutOfBounds => write!(f, "Texture region lies outside of the texture"),
+            DrawerError::ImageSize { expected, actual } => write!(f, "Image data is {} bytes long, expected {}", actual, expected),
+            DrawerError::Font(index) => write!(f, "Font {} could not be loaded", index),
+            DrawerError::TextureSlots => write!(f, "No texture slot left for a new handle"),
+        }
+    }
+}
+
+impl Error for DrawerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DrawerError::Program(ref e) => Some(e),
+            DrawerError::Texture(ref e) => Some(e),
+            DrawerError::VertexBuffer(ref e) => Some(e),
+            DrawerError::IndexBuffer(ref e) => Some(e),
+            DrawerError::Draw(ref e) => Some(e),
+            DrawerError::MissingTexture(_) | DrawerError::RegionOutOfBounds | DrawerError::ImageSize { .. } | DrawerError::Font(_) | DrawerError::TextureSlots => None,
+        }
+    }
+}
+
+impl From<glium::ProgramCreationError> for DrawerError {
+    fn from(e: glium::ProgramCreationError) -> DrawerError {
+        DrawerError::Program(e)
+    }
+}
+
+impl From<glium::texture::TextureCreationError> for DrawerError {
+    fn from(e: glium::texture::TextureCreationError) -> DrawerError {
+        DrawerError::Texture(e)
+    }
+}
+
+impl From<glium::vertex::BufferCreationError> for DrawerError {
+    fn from(e: glium::vertex::BufferCreationError) -> DrawerError {
+        DrawerError::VertexBuffer(e)
+    }
+}
+
+impl From<glium::index::BufferCreationError> for DrawerError {
+    fn from(e: glium::index::BufferCreationError) -> DrawerError {
+        DrawerError::IndexBuffer(e)
+    }
+}
+
+impl From<glium::DrawError> for DrawerError {
+    fn from(e: glium::DrawError) -> DrawerError {
+        DrawerError::Draw(e)
+    }
+}
+
+/// What `Drawer::draw` does with a command whose texture handle is not registered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MissingTexturePolicy {
+    /// Drop the command and keep drawing.
+    Skip,
+    /// Draw the command with a magenta placeholder texture.
+    #[default]
+    Placeholder,
+    /// Abort the frame with `DrawerError::MissingTexture`.
+    Error,
+}
+
+/// How `Drawer::draw` treats the alpha it writes into the target.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// Classic `src * a + dst * (1 - a)` blending, fine for opaque targets.
+    #[default]
+    Straight,
+    /// Writes premultiplied colors and accumulates alpha correctly, for transparent windows and render targets that get composited later.
+    Premultiplied,
+}
+
+/// Color space `Drawer::draw` blends in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ColorPipeline {
+    /// The reference nuklear look: colors and texels are written unchanged and blended as sRGB-encoded values,
+    /// on sRGB surfaces too.
+    #[default]
+    Gamma,
+    /// sRGB-correct rendering: vertex colors are decoded in the vertex shader, color textures added from now on
+    /// are uploaded as `SrgbTexture2d`, and filtering and blending happen in linear space.
+    ///
+    /// Set `srgb_surface` when the target encodes on write (an sRGB default framebuffer or `SrgbTexture2d`);
+    /// otherwise the shader encodes, and blending then works on encoded values.
+    Srgb { srgb_surface: bool },
+}
+
+/// How `Drawer::draw` confines each command to its nuklear clip rectangle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ClipMode {
+    /// `DrawParameters::scissor`, cheapest, but only right for screen-space drawing. Transformed drawing falls back to `Shader`.
+    #[default]
+    Scissor,
+    /// Discards fragments outside the rectangle, tested in nuklear coordinates in the fragment shader.
+    Shader,
+    /// Writes each rectangle into the stencil buffer and tests against it. The surface needs a stencil buffer, which gets cleared.
+    Stencil,
+}
+
+/// How `Drawer::draw` streams the converted geometry to the GPU.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Streaming {
+    /// One buffer pair, orphaned with `invalidate` before every upload so the driver can hand out fresh storage.
+    #[default]
+    Orphan,
+    /// This many buffer pairs used in turn. Each is fenced after drawing and only rewritten once the GPU has passed the fence.
+    Ring(usize),
+    /// Like `Ring`, with persistently mapped buffers that are written without a driver copy.
+    /// Needs GL 4.4 or `ARB_buffer_storage`, glium falls back to regular buffers otherwise.
+    Persistent(usize),
+}
+
+impl Streaming {
+    fn buffers(self) -> usize {
+        match self {
+            Streaming::Orphan => 1,
+            Streaming::Ring(n) | Streaming::Persistent(n) => n.max(1),
+        }
+    }
+}
+
+/// Placement of the UI for `Drawer::draw_with_transform`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    /// Maps nuklear logical pixels (x right, y down, z = 0) to clip space, e.g. projection * view * model of a quad in a 3D scene.
+    pub matrix: [[f32; 4]; 4],
+    /// Tests and writes the depth buffer of the surface, which then needs one.
+    pub depth_test: bool,
+}
+
+/// What the last `Drawer::draw` did, see `Drawer::stats`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct DrawStats {
+    /// Nuklear draw commands in the frame, empty ones included.
+    pub commands: usize,
+    /// `Surface::draw` calls issued, clipping and depth passes included.
+    pub draw_calls: usize,
+    /// Vertices nuklear converted.
+    pub vertices: usize,
+    /// Indices nuklear converted.
+    pub indices: usize,
+    /// Draw calls that bound a different texture than the previous one.
+    pub texture_switches: usize,
+    /// Draw calls saved by merging adjacent commands that share a texture and clip rectangle.
+    pub merged: usize,
+    /// Commands dropped because their clip rectangle is empty or off the surface.
+    pub culled: usize,
+    /// Bytes written into GPU buffers.
+    pub bytes_uploaded: usize,
+    /// Vertex half of `Drawer::peak_buffer_usage`.
+    pub peak_vertices: usize,
+    /// Index half of `Drawer::peak_buffer_usage`.
+    pub peak_indices: usize,
+    /// GPU time of the most recent frame the GPU has finished, usually an earlier one, when `Drawer::set_gpu_timing` is on.
+    pub gpu_time: Option<Duration>,
+}
+
+/// A frame converted by `Drawer::prepare`: vertices, indices and the texture, clip rectangle and index range of every command.
+///
+/// It no longer borrows the nuklear `Context`, and can be rendered any number of times with the drawer that prepared it.
+#[derive(Clone, Default)]
+pub struct DrawList {
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+    commands: Vec<Batch>,
+    key: Option<u64>,
+}
+
+impl DrawList {
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Nuklear draw commands, empty ones included.
+    pub fn command_count(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+struct DrawUniforms<'a> {
+    proj: [[f32; 4]; 4],
+    tex: TextureRef<'a>,
+    premultiply: bool,
+    color: ColorPipeline,
+    clip: Option<[f32; 4]>,
+}
+
+impl<'a> Uniforms for DrawUniforms<'a> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut visit: F) {
+        visit("ProjMtx", UniformValue::Mat4(self.proj));
+        visit("Texture", self.tex.uniform());
+        visit("Swizzle", UniformValue::SignedInt(self.tex.swizzle()));
+        visit("Premultiply", UniformValue::Bool(self.premultiply));
+        visit("LinearColors", UniformValue::Bool(self.color != ColorPipeline::Gamma));
+        visit("TexelConvert", UniformValue::SignedInt(self.tex.texel_convert(self.color != ColorPipeline::Gamma)));
+        visit("EncodeOutput", UniformValue::Bool(self.color == ColorPipeline::Srgb { srgb_surface: false }));
+        visit("ClipEnabled", UniformValue::Bool(self.clip.is_some()));
+        visit("ClipRect", UniformValue::Vec4(self.clip.unwrap_or_default()));
+    }
+}
+
 pub struct Drawer {
     cmd: Buffer,
     prg: glium::Program,
-    tex: Vec<glium::Texture2d>,
+    prg_srgb: glium::Program,
+    tex: Vec<TextureSlot>,
+    free: Vec<usize>,
+    missing_tex: Texture,
+    missing_policy: MissingTexturePolicy,
+    missing_reported: HashSet<i32>,
+    alpha_mode: AlphaMode,
+    color: ColorPipeline,
+    clip_mode: ClipMode,
+    peak_vbo: usize,
+    peak_ebo: usize,
+    stats: DrawStats,
+    gpu_timing: bool,
+    timer: Option<glium::draw_parameters::TimeElapsedQuery>,
     vbf: Vec<Vertex>,
     ebf: Vec<u16>,
-    vbo: glium::VertexBuffer<Vertex>,
-    ebo: glium::IndexBuffer<u16>,
+    streaming: Streaming,
+    slots: Vec<Slot>,
+    slot: usize,
+    context: Rc<glium::backend::Context>,
+    clip_vbo: glium::VertexBuffer<Vertex>,
     vle: DrawVertexLayoutElements,
+    list: DrawList,
+    converted: Option<u64>,
+    dirty: bool,
 }
 
 impl Drawer {
-    pub fn new(display: &mut glium::Display, texture_count: usize, vbo_size: usize, ebo_size: usize, command_buffer: Buffer) -> Drawer {
-        Drawer {
+    /// `vbo_size` and `ebo_size` are the initial buffer capacities in vertices and indices, both grow when a frame needs more.
+    /// Nuklear-sys builds nuklear with 16 bit indices, so a single frame is limited to 65 535 vertices.
+    pub fn new<F: Facade + ?Sized>(facade: &F, texture_count: usize, vbo_size: usize, ebo_size: usize, command_buffer: Buffer) -> Drawer {
+        Drawer::try_new(facade, texture_count, vbo_size, ebo_size, command_buffer).unwrap()
+    }
+
+    pub fn try_new<F: Facade + ?Sized>(facade: &F, texture_count: usize, vbo_size: usize, ebo_size: usize, command_buffer: Buffer) -> Result<Drawer, DrawerError> {
+        let slot = Slot {
+            vbo: vertex_buffer(facade, vbo_size, false)?,
+            ebo: element_buffer(facade, ebo_size, false)?,
+            fence: None,
+        };
+
+        Ok(Drawer {
             cmd: command_buffer,
-            prg: glium::Program::from_source(display, VS, FS, None).unwrap(),
+            prg: program(facade, true)?,
+            prg_srgb: program(facade, false)?,
             tex: Vec::with_capacity(texture_count + 1),
-            vbf: vec![Vertex::default(); vbo_size * ::std::mem::size_of::<Vertex>()],
-            ebf: vec![0u16; ebo_size * ::std::mem::size_of::<u16>()],
-            vbo: glium::VertexBuffer::empty_dynamic(display, vbo_size * ::std::mem::size_of::<Vertex>()).unwrap(),
-            ebo: glium::IndexBuffer::empty_dynamic(display, glium::index::PrimitiveType::TrianglesList, ebo_size * ::std::mem::size_of::<u16>()).unwrap(),
+            free: Vec::new(),
+            missing_tex: Texture::new(facade, &[255, 0, 255, 255], 1, 1, TextureOptions::default(), false)?,
+            missing_policy: MissingTexturePolicy::default(),
+            missing_reported: HashSet::new(),
+            alpha_mode: AlphaMode::default(),
+            color: ColorPipeline::default(),
+            clip_mode: ClipMode::default(),
+            peak_vbo: 0,
+            peak_ebo: 0,
+            stats: DrawStats::default(),
+            gpu_timing: false,
+            timer: None,
+            vbf: vec![Vertex::default(); vbo_size],
+            ebf: vec![0; ebo_size],
+            streaming: Streaming::default(),
+            slots: vec![slot],
+            slot: 0,
+            context: facade.get_context().clone(),
+            clip_vbo: glium::VertexBuffer::empty_dynamic(facade, 4)?,
             vle: DrawVertexLayoutElements::new(&[
                 (DrawVertexLayoutAttribute::Position, DrawVertexLayoutFormat::Float, 0),
                 (DrawVertexLayoutAttribute::TexCoord, DrawVertexLayoutFormat::Float, 8),
                 (DrawVertexLayoutAttribute::Color, DrawVertexLayoutFormat::R8G8B8A8, 16),
                 (DrawVertexLayoutAttribute::AttributeCount, DrawVertexLayoutFormat::Count, 32),
             ]),
-        }
+            list: DrawList::default(),
+            converted: None,
+            dirty: false,
+        })
+    }
+
+    pub fn add_texture<F: Facade + ?Sized>(&mut self, facade: &F, image: &[u8], width: u32, height: u32) -> Handle {
+        self.try_add_texture(facade, image, width, height).unwrap()
+    }
+
+    pub fn try_add_texture<F: Facade + ?Sized>(&mut self, facade: &F, image: &[u8], width: u32, height: u32) -> Result<Handle, DrawerError> {
+        self.try_add_texture_with(facade, image, width, height, TextureOptions::default())
     }
 
-    pub fn add_texture(&mut self, display: &mut glium::Display, image: &[u8], width: u32, height: u32) -> Handle {
-        let image = glium::texture::RawImage2d {
-            data: std::borrow::Cow::Borrowed(image),
-            width: width,
-            height: height,
-            format: glium::texture::ClientFormat::U8U8U8U8,
+    pub fn add_texture_with<F: Facade + ?Sized>(&mut self, facade: &F, image: &[u8], width: u32, height: u32, options: TextureOptions) -> Handle {
+        self.try_add_texture_with(facade, image, width, height, options).unwrap()
+    }
+
+    pub fn try_add_texture_with<F: Facade + ?Sized>(&mut self, facade: &F, image: &[u8], width: u32, height: u32, options: TextureOptions) -> Result<Handle, DrawerError> {
+        let srgb = self.color != ColorPipeline::Gamma;
+        let tex = Texture::new(facade, image, width, height, options, srgb)?;
+        self.insert_texture(tex)
+    }
+
+    /// Registers a texture rendered or owned elsewhere, e.g. an offscreen 3D viewport, without copying it.
+    ///
+    /// The drawer keeps its `Rc` until `remove_texture` is called.
+    pub fn add_shared_texture(&mut self, texture: Rc<glium::Texture2d>) -> Handle {
+        self.try_add_shared_texture(texture).unwrap()
+    }
+
+    pub fn try_add_shared_texture(&mut self, texture: Rc<glium::Texture2d>) -> Result<Handle, DrawerError> {
+        self.insert_texture(Texture::shared(texture))
+    }
+
+    fn insert_texture(&mut self, tex: Texture) -> Result<Handle, DrawerError> {
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.tex[slot].tex = Some(tex);
+                slot
+            }
+            None if self.tex.len() < MAX_SLOTS => {
+                self.tex.push(TextureSlot { tex: Some(tex), generation: 0 });
+                self.tex.len() - 1
+            }
+            None => return Err(DrawerError::TextureSlots),
         };
-        let tex = glium::Texture2d::new(display, image).unwrap();
-        let hnd = Handle::from_id(self.tex.len() as i32 + 1);
-        self.tex.push(tex);
-        hnd
+        Ok(Handle::from_id(slot_id(slot, self.tex[slot].generation)))
+    }
+
+    /// Frees the texture behind `handle`. Returns `false` if the handle is unknown or was already removed.
+    ///
+    /// The slot is reused by a later `add_texture` under a new generation, so the old handle stays invalid.
+    /// A slot whose generations are used up is retired instead.
+    pub fn remove_texture(&mut self, handle: Handle) -> bool {
+        match find_slot(&self.tex, handle_id(handle)) {
+            Some(slot) => {
+                if self.tex[slot].release() {
+                    self.free.push(slot);
+                }
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Swaps the image behind a live `handle`, which keeps working in widgets that already reference it.
+    ///
+    /// The image is read in the format the texture was registered with.
+    pub fn replace_texture<F: Facade + ?Sized>(&mut self, facade: &F, handle: Handle, image: &[u8], width: u32, height: u32) -> Result<(), DrawerError> {
+        let id = handle_id(handle);
+        let slot = find_slot(&self.tex, id).ok_or(DrawerError::MissingTexture(id))?;
+        let old = self.tex[slot].tex.as_ref().unwrap();
+        let (options, srgb) = (*old.options(), old.is_srgb());
+        self.tex[slot].tex = Some(Texture::new(facade, image, width, height, options, srgb)?);
+        self.dirty = true;
+        Ok(())
     }
 
-    pub fn draw(&mut self, ctx: &mut Context, cfg: &mut ConvertConfig, frame: &mut glium::Frame, _scale: Vec2) {
-        use glium::uniforms::MagnifySamplerFilter;
-        use glium::Surface;
-        use glium::{Blend, DrawParameters, Rect};
+    /// Overwrites a `width` x `height` block of pixels at `x`, `y`, with rows in the same order and format as the data given to `add_texture`.
+    pub fn update_texture_region(&mut self, handle: Handle, x: u32, y: u32, width: u32, height: u32, data: &[u8]) -> Result<(), DrawerError> {
+        let id = handle_id(handle);
+        let tex = find_res(&self.tex, id).ok_or(DrawerError::MissingTexture(id))?;
+        tex.write(x, y, width, height, data)?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn missing_texture_policy(&self) -> MissingTexturePolicy {
+        self.missing_policy
+    }
+
+    pub fn set_missing_texture_policy(&mut self, policy: MissingTexturePolicy) {
+        self.dirty |= self.missing_policy != policy;
+        self.missing_policy = policy;
+    }
+
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    pub fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        self.dirty |= self.alpha_mode != mode;
+        self.alpha_mode = mode;
+    }
+
+    pub fn color_pipeline(&self) -> ColorPipeline {
+        self.color
+    }
+
+    /// Textures already registered keep their storage and are converted in the shader instead.
+    pub fn set_color_pipeline(&mut self, color: ColorPipeline) {
+        self.dirty |= self.color != color;
+        self.color = color;
+    }
+
+    pub fn clip_mode(&self) -> ClipMode {
+        self.clip_mode
+    }
+
+    pub fn set_clip_mode(&mut self, mode: ClipMode) {
+        self.dirty |= self.clip_mode != mode;
+        self.clip_mode = mode;
+    }
+
+    pub fn stats(&self) -> &DrawStats {
+        &self.stats
+    }
+
+    pub fn gpu_timing(&self) -> bool {
+        self.gpu_timing
+    }
+
+    /// Measures GPU time with a `TimeElapsedQuery`, read back without stalling once the GPU is done with it.
+    ///
+    /// Stays `None` in `DrawStats::gpu_time` where timer queries are unsupported.
+    pub fn set_gpu_timing(&mut self, enabled: bool) {
+        self.gpu_timing = enabled;
+        if !enabled {
+            self.timer = None;
+        }
+    }
+
+    pub fn streaming(&self) -> Streaming {
+        self.streaming
+    }
+
+    /// Buffers of the previous strategy are dropped, the new ones are created as frames need them.
+    pub fn set_streaming(&mut self, streaming: Streaming) {
+        if self.streaming != streaming {
+            self.streaming = streaming;
+            self.slots.clear();
+            self.converted = None;
+        }
+    }
+
+    /// Whether drawing `ctx` with `cfg` would change anything since the last draw, so apps with a static UI can skip the frame and its buffer swap.
+    ///
+    /// Compares the commands nuklear recorded, call it after building the UI and before `Context::clear`.
+    /// Settings of the drawer and textures changed through it are tracked too. The contents of shared textures,
+    /// `FrameTextures`, the scale, transforms and surface resizes are not, call `request_redraw` when those change.
+    pub fn needs_redraw(&self, ctx: &mut Context, cfg: &ConvertConfig) -> bool {
+        let mut cfg = cfg.clone();
+        cfg.set_vertex_layout(&self.vle);
+        cfg.set_vertex_size(::std::mem::size_of::<Vertex>());
+        self.dirty || self.converted.is_none() || self.converted != frame_key(ctx, &cfg)
+    }
 
+    /// Makes `needs_redraw` return `true` until the next draw, e.g. after rendering into a texture registered with `add_shared_texture`.
+    pub fn request_redraw(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Largest vertex and element counts a single frame has needed so far, in vertices and indices.
+    pub fn peak_buffer_usage(&self) -> (usize, usize) {
+        (self.peak_vbo, self.peak_ebo)
+    }
+
+    pub fn draw<S: glium::Surface>(&mut self, ctx: &mut Context, cfg: &mut ConvertConfig, frame: &mut S, scale: Vec2) {
+        self.try_draw(ctx, cfg, frame, scale).unwrap()
+    }
+
+    pub fn try_draw<S: glium::Surface>(&mut self, ctx: &mut Context, cfg: &mut ConvertConfig, frame: &mut S, scale: Vec2) -> Result<(), DrawerError> {
+        self.try_draw_with(ctx, cfg, frame, scale, &FrameTextures::new())
+    }
+
+    pub fn draw_with<S: glium::Surface>(&mut self, ctx: &mut Context, cfg: &mut ConvertConfig, frame: &mut S, scale: Vec2, textures: &FrameTextures) {
+        self.try_draw_with(ctx, cfg, frame, scale, textures).unwrap()
+    }
+
+    /// Like `try_draw`, additionally resolving the handles of `textures` lent for this frame only.
+    ///
+    /// Renders into any glium surface, e.g. a `Frame` or an offscreen `SimpleFrameBuffer`.
+    /// Nuklear coordinates are logical pixels; `scale` is the number of surface pixels per logical pixel.
+    pub fn try_draw_with<S: glium::Surface>(&mut self, ctx: &mut Context, cfg: &mut ConvertConfig, frame: &mut S, scale: Vec2, textures: &FrameTextures) -> Result<(), DrawerError> {
+        let list = self.prepare_cached(ctx, cfg);
+        let result = self.try_render_with(&list, frame, scale, textures);
+        self.list = list;
+        result
+    }
+
+    pub fn draw_with_transform<S: glium::Surface>(&mut self, ctx: &mut Context, cfg: &mut ConvertConfig, frame: &mut S, transform: &Transform, textures: &FrameTextures) {
+        self.try_draw_with_transform(ctx, cfg, frame, transform, textures).unwrap()
+    }
+
+    /// Like `try_draw_with`, but places the UI with a caller-supplied matrix instead of the screen-space projection.
+    ///
+    /// Clip rectangles are applied in the fragment shader in nuklear coordinates, so they follow any transform.
+    pub fn try_draw_with_transform<S: glium::Surface>(&mut self, ctx: &mut Context, cfg: &mut ConvertConfig, frame: &mut S, transform: &Transform, textures: &FrameTextures) -> Result<(), DrawerError> {
+        let list = self.prepare_cached(ctx, cfg);
+        let result = self.try_render_with_transform(&list, frame, transform, textures);
+        self.list = list;
+        result
+    }
+
+    /// Converts the commands `ctx` recorded into a `DrawList`, which can happen before the frame begins.
+    pub fn prepare(&mut self, ctx: &mut Context, cfg: &ConvertConfig) -> DrawList {
+        let mut list = DrawList::default();
+        self.prepare_into(ctx, cfg, &mut list);
+        list
+    }
+
+    pub fn render<S: glium::Surface>(&mut self, list: &DrawList, frame: &mut S, scale: Vec2) {
+        self.try_render(list, frame, scale).unwrap()
+    }
+
+    pub fn try_render<S: glium::Surface>(&mut self, list: &DrawList, frame: &mut S, scale: Vec2) -> Result<(), DrawerError> {
+        self.try_render_with(list, frame, scale, &FrameTextures::new())
+    }
+
+    pub fn render_with<S: glium::Surface>(&mut self, list: &DrawList, frame: &mut S, scale: Vec2, textures: &FrameTextures) {
+        self.try_render_with(list, frame, scale, textures).unwrap()
+    }
+
+    /// Like `try_draw_with` for a list from `prepare`. Rendering the same list again, e.g. for the other eye or a mirror window,
+    /// does not upload its geometry again.
+    pub fn try_render_with<S: glium::Surface>(&mut self, list: &DrawList, frame: &mut S, scale: Vec2, textures: &FrameTextures) -> Result<(), DrawerError> {
         let (ww, hh) = frame.get_dimensions();
 
         let ortho = [
-            [2.0f32 / ww as f32, 0.0f32, 0.0f32, 0.0f32],
-            [0.0f32, -2.0f32 / hh as f32, 0.0f32, 0.0f32],
+            [2.0f32 * scale.x / ww as f32, 0.0f32, 0.0f32, 0.0f32],
+            [0.0f32, -2.0f32 * scale.y / hh as f32, 0.0f32, 0.0f32],
             [0.0f32, 0.0f32, -1.0f32, 0.0f32],
             [-1.0f32, 1.0f32, 0.0f32, 1.0f32],
         ];
 
+        self.submit(list, frame, textures, ortho, Placement::Screen(scale, (ww, hh)))
+    }
+
+    pub fn render_with_transform<S: glium::Surface>(&mut self, list: &DrawList, frame: &mut S, transform: &Transform, textures: &FrameTextures) {
+        self.try_render_with_transform(list, frame, transform, textures).unwrap()
+    }
+
+    /// Like `try_draw_with_transform` for a list from `prepare`.
+    pub fn try_render_with_transform<S: glium::Surface>(&mut self, list: &DrawList, frame: &mut S, transform: &Transform, textures: &FrameTextures) -> Result<(), DrawerError> {
+        self.submit(list, frame, textures, transform.matrix, Placement::World(transform.depth_test))
+    }
+
+    // `draw` converts into a list kept by the drawer, so frames with unchanged commands are neither converted nor uploaded again.
+    fn prepare_cached(&mut self, ctx: &mut Context, cfg: &ConvertConfig) -> DrawList {
+        let mut list = ::std::mem::take(&mut self.list);
+        self.prepare_into(ctx, cfg, &mut list);
+        list
+    }
+
+    fn prepare_into(&mut self, ctx: &mut Context, cfg: &ConvertConfig, list: &mut DrawList) {
+        let mut cfg = cfg.clone();
         cfg.set_vertex_layout(&self.vle);
         cfg.set_vertex_size(::std::mem::size_of::<Vertex>());
 
-        {
-            self.vbo.invalidate();
-            self.ebo.invalidate();
+        // The same commands convert to the same geometry.
+        let key = frame_key(ctx, &cfg);
+        if key.is_some() && key == list.key {
+            return;
+        }
+
+        let (vertices, indices) = loop {
+            unsafe {
+                nuklear::nuklear_sys::nk_buffer_clear(self.cmd.as_mut());
+            }
 
-            let mut rvbuf = unsafe { ::std::slice::from_raw_parts_mut(self.vbf.as_mut() as *mut [Vertex] as *mut u8, self.vbf.capacity()) };
-            let mut rebuf = unsafe { ::std::slice::from_raw_parts_mut(self.ebf.as_mut() as *mut [u16] as *mut u8, self.ebf.capacity()) };
-            let mut vbuf = Buffer::with_fixed(&mut rvbuf);
-            let mut ebuf = Buffer::with_fixed(&mut rebuf);
+            let (vneeded, eneeded) = {
+                let rvbuf = unsafe { ::std::slice::from_raw_parts_mut(self.vbf.as_mut() as *mut [Vertex] as *mut u8, ::std::mem::size_of_val(&self.vbf[..])) };
+                let rebuf = unsafe { ::std::slice::from_raw_parts_mut(self.ebf.as_mut() as *mut [u16] as *mut u8, ::std::mem::size_of_val(&self.ebf[..])) };
+                let mut vbuf = Buffer::with_fixed(rvbuf);
+                let mut ebuf = Buffer::with_fixed(rebuf);
 
-            ctx.convert(&mut self.cmd, &mut vbuf, &mut ebuf, &cfg);
+                ctx.convert(&mut self.cmd, &mut vbuf, &mut ebuf, &cfg);
 
-            self.vbo.slice_mut(0..self.vbf.capacity()).unwrap().write(&self.vbf);
-            self.ebo.slice_mut(0..self.ebf.capacity()).unwrap().write(&self.ebf);
-        }
+                (vbuf.info().2, ebuf.info().2)
+            };
 
-        let mut idx_start = 0;
-        let mut idx_end;
+            let vbo_size = self.vbf.len();
+            let ebo_size = self.ebf.len();
+            let vneeded = vneeded.div_ceil(::std::mem::size_of::<Vertex>());
+            let eneeded = eneeded.div_ceil(::std::mem::size_of::<u16>());
 
+            self.peak_vbo = self.peak_vbo.max(vneeded);
+            self.peak_ebo = self.peak_ebo.max(eneeded);
+
+            if vneeded <= vbo_size && eneeded <= ebo_size {
+                break (vneeded, eneeded);
+            }
+
+            if vneeded > vbo_size {
+                let vbo_size = vneeded.max(vbo_size * 2);
+                info!("Growing nuklear vertex buffer to {} vertices", vbo_size);
+                self.vbf = vec![Vertex::default(); vbo_size];
+            }
+            if eneeded > ebo_size {
+                let ebo_size = eneeded.max(ebo_size * 2);
+                info!("Growing nuklear element buffer to {} elements", ebo_size);
+                self.ebf = vec![0; ebo_size];
+            }
+        };
+
+        list.vertices.clear();
+        list.vertices.extend_from_slice(&self.vbf[..vertices]);
+        list.indices.clear();
+        list.indices.extend_from_slice(&self.ebf[..indices]);
+
+        list.commands.clear();
+        let mut idx = 0;
         for cmd in ctx.draw_command_iterator(&self.cmd) {
-            if cmd.elem_count() < 1 {
+            let range = idx..idx + cmd.elem_count() as usize;
+            idx = range.end;
+            list.commands.push(Batch {
+                range: range,
+                id: handle_id(cmd.texture()),
+                clip: clip_rect(cmd.clip_rect()),
+            });
+        }
+        list.key = key;
+    }
+
+    fn submit<S: glium::Surface>(&mut self, list: &DrawList, frame: &mut S, textures: &FrameTextures, proj: [[f32; 4]; 4], placement: Placement) -> Result<(), DrawerError> {
+        use glium::index::{NoIndices, PrimitiveType};
+        use glium::{Depth, DepthTest, DrawParameters, StencilOperation, StencilTest};
+
+        let clip_mode = match (placement, self.clip_mode) {
+            (Placement::World(_), ClipMode::Scissor) => ClipMode::Shader,
+            (_, mode) => mode,
+        };
+        let mut stencil_ref = 0;
+        let mut stencil_clip = None;
+        if clip_mode == ClipMode::Stencil {
+            frame.clear_stencil(0);
+        }
+
+        let mut stats = DrawStats {
+            gpu_time: if self.gpu_timing { self.stats.gpu_time } else { None },
+            ..DrawStats::default()
+        };
+        if let Some(timer) = self.timer.take() {
+            if timer.is_ready() {
+                stats.gpu_time = Some(Duration::from_nanos(timer.get() as u64));
+            } else {
+                self.timer = Some(timer);
+            }
+        }
+        // One query in flight at a time, so frames are skipped rather than waited for.
+        let timer = if self.gpu_timing && self.timer.is_none() {
+            glium::draw_parameters::TimeElapsedQuery::new(&self.context).ok()
+        } else {
+            None
+        };
+
+        // Geometry already in the buffers is not uploaded again.
+        let (vertices, indices) = (list.vertices.len(), list.indices.len());
+        if list.key.is_none() || list.key != self.converted {
+            let persistent = matches!(self.streaming, Streaming::Persistent(_));
+            let (vbo_size, ebo_size) = (self.vbf.len().max(vertices), self.ebf.len().max(indices));
+            let next = if self.slots.is_empty() { 0 } else { (self.slot + 1) % self.streaming.buffers() };
+            if next == self.slots.len() {
+                self.slots.push(Slot {
+                    vbo: vertex_buffer(&self.context, vbo_size, persistent)?,
+                    ebo: element_buffer(&self.context, ebo_size, persistent)?,
+                    fence: None,
+                });
+            }
+            self.slot = next;
+
+            let slot = &mut self.slots[next];
+            if let Some(fence) = slot.fence.take() {
+                fence.wait();
+            }
+            // GPU buffers follow the CPU side when a frame outgrew them.
+            if slot.vbo.len() < vertices {
+                slot.vbo = vertex_buffer(&self.context, vbo_size, persistent)?;
+            }
+            if slot.ebo.len() < indices {
+                slot.ebo = element_buffer(&self.context, ebo_size, persistent)?;
+            }
+            if self.streaming == Streaming::Orphan {
+                slot.vbo.invalidate();
+                slot.ebo.invalidate();
+            }
+            // glium panics when writing an empty slice, which is what a frame without windows produces.
+            if vertices > 0 {
+                slot.vbo.slice_mut(0..vertices).unwrap().write(&list.vertices);
+            }
+            if indices > 0 {
+                slot.ebo.slice_mut(0..indices).unwrap().write(&list.indices);
+            }
+            stats.bytes_uploaded = vertices * ::std::mem::size_of::<Vertex>() + indices * ::std::mem::size_of::<u16>();
+            self.converted = list.key;
+        }
+        stats.vertices = vertices;
+        stats.indices = indices;
+        stats.peak_vertices = self.peak_vbo;
+        stats.peak_indices = self.peak_ebo;
+        let mut last_texture = None;
+
+        let mut batches: Vec<Batch> = Vec::new();
+        for command in &list.commands {
+            stats.commands += 1;
+            if command.range.is_empty() {
                 continue;
             }
 
-            let id = cmd.texture().id().unwrap();
-            let ptr = self.find_res(id).unwrap();
+            let [x0, y0, x1, y1] = command.clip;
+            let empty = match placement {
+                Placement::Screen(scale, size) => {
+                    let rect = scissor(command.clip, scale, size);
+                    rect.width == 0 || rect.height == 0
+                }
+                Placement::World(_) => x1 <= x0 || y1 <= y0,
+            };
+            if empty {
+                stats.culled += 1;
+                continue;
+            }
 
-            idx_end = idx_start + cmd.elem_count() as usize;
+            match batches.last_mut() {
+                Some(batch) if batch.id == command.id && batch.clip == command.clip && batch.range.end == command.range.start => {
+                    batch.range.end = command.range.end;
+                    stats.merged += 1;
+                }
+                _ => batches.push(command.clone()),
+            }
+        }
+
+        let slot = &self.slots[self.slot];
+        let mut depth_pass = Vec::new();
+        for Batch { range, id, clip } in batches {
+            let ptr = match resolve(&self.tex, textures, id) {
+                Some(ptr) => ptr,
+                None => {
+                    if self.missing_reported.insert(id) {
+                        warn!("nuklear draw command references unknown texture handle id {}", id);
+                    }
+                    match self.missing_policy {
+                        MissingTexturePolicy::Skip => continue,
+                        MissingTexturePolicy::Placeholder => TextureRef::Registered(&self.missing_tex),
+                        MissingTexturePolicy::Error => return Err(DrawerError::MissingTexture(id)),
+                    }
+                }
+            };
 
-            let x = cmd.clip_rect().x;
-            let y = cmd.clip_rect().y;
-            let w = cmd.clip_rect().w;
-            let h = cmd.clip_rect().h;
+            if clip_mode == ClipMode::Stencil && stencil_clip != Some(clip) {
+                if stencil_ref == 255 {
+                    frame.clear_stencil(0);
+                    stencil_ref = 0;
+                }
+                stencil_ref += 1;
+                stencil_clip = Some(clip);
 
-            frame
-                .draw(
-                    &self.vbo,
-                    &self.ebo.slice(idx_start..idx_end).unwrap(),
+                self.clip_vbo.write(&clip_quad(clip));
+                stats.bytes_uploaded += 4 * ::std::mem::size_of::<Vertex>();
+                frame.draw(
+                    &self.clip_vbo,
+                    NoIndices(PrimitiveType::TriangleStrip),
                     &self.prg,
-                    &uniform! {
-                        ProjMtx: ortho,
-                        Texture: ptr.sampled().magnify_filter(MagnifySamplerFilter::Linear),
+                    &DrawUniforms {
+                        proj: proj,
+                        tex: TextureRef::Registered(&self.missing_tex),
+                        premultiply: false,
+                        color: self.color,
+                        clip: None,
                     },
                     &DrawParameters {
-                        blend: Blend::alpha_blending(),
-                        scissor: Some(Rect {
-                            left: (if x < 0f32 { 0f32 } else { x }) as u32,
-                            bottom: (if y < 0f32 { 0f32 } else { hh as f32 - y - h }) as u32,
-                            width: (if x < 0f32 { w + x } else { w }) as u32,
-                            height: (if y < 0f32 { h + y } else { h }) as u32,
-                        }),
+                        color_mask: (false, false, false, false),
+                        stencil: stencil(StencilTest::AlwaysPass, stencil_ref, StencilOperation::Replace),
                         backface_culling: glium::draw_parameters::BackfaceCullingMode::CullingDisabled,
+                        time_elapsed_query: timer.as_ref(),
 
                         ..DrawParameters::default()
                     },
-                )
-                .unwrap();
-            idx_start = idx_end;
+                )?;
+                stats.draw_calls += 1;
+            }
+
+            if last_texture != Some(id) {
+                stats.texture_switches += 1;
+                last_texture = Some(id);
+            }
+
+            frame.draw(
+                &slot.vbo,
+                slot.ebo.slice(range.clone()).unwrap(),
+                // With `outputs_srgb` unset glium enables GL_FRAMEBUFFER_SRGB, making sRGB surfaces encode on write.
+                if self.color == (ColorPipeline::Srgb { srgb_surface: true }) { &self.prg_srgb } else { &self.prg },
+                &DrawUniforms {
+                    proj: proj,
+                    tex: ptr,
+                    premultiply: self.alpha_mode == AlphaMode::Premultiplied || ptr.blend_mode() == BlendMode::Multiply,
+                    color: self.color,
+                    clip: if clip_mode == ClipMode::Shader { Some(clip) } else { None },
+                },
+                &DrawParameters {
+                    blend: blend(self.alpha_mode, ptr.blend_mode()),
+                    scissor: match placement {
+                        Placement::Screen(scale, size) if clip_mode == ClipMode::Scissor => Some(scissor(clip, scale, size)),
+                        _ => None,
+                    },
+                    stencil: if clip_mode == ClipMode::Stencil {
+                        stencil(StencilTest::IfEqual { mask: 0xFF }, stencil_ref, StencilOperation::Keep)
+                    } else {
+                        Default::default()
+                    },
+                    // Coplanar UI layers would fight over depth, so the color pass only tests against the scene.
+                    depth: match placement {
+                        Placement::World(true) => Depth { test: DepthTest::IfLess, ..Depth::default() },
+                        _ => Depth::default(),
+                    },
+                    backface_culling: glium::draw_parameters::BackfaceCullingMode::CullingDisabled,
+                    time_elapsed_query: timer.as_ref(),
+
+                    ..DrawParameters::default()
+                },
+            )?;
+            stats.draw_calls += 1;
+            if let Placement::World(true) = placement {
+                depth_pass.push((range, clip));
+            }
         }
+
+        // Then the UI writes its depth once painted, occluding whatever is drawn behind it later.
+        for (range, clip) in depth_pass {
+            frame.draw(
+                &slot.vbo,
+                slot.ebo.slice(range).unwrap(),
+                &self.prg,
+                &DrawUniforms {
+                    proj: proj,
+                    tex: TextureRef::Registered(&self.missing_tex),
+                    premultiply: false,
+                    color: self.color,
+                    clip: Some(clip),
+                },
+                &DrawParameters {
+                    depth: Depth {
+                        test: DepthTest::IfLess,
+                        write: true,
+                        ..Depth::default()
+                    },
+                    color_mask: (false, false, false, false),
+                    backface_culling: glium::draw_parameters::BackfaceCullingMode::CullingDisabled,
+                    time_elapsed_query: timer.as_ref(),
+
+                    ..DrawParameters::default()
+                },
+            )?;
+            stats.draw_calls += 1;
+        }
+
+        // A query that never enclosed a draw call never becomes ready, so keeping it would stall timing for good.
+        if let Some(timer) = timer.filter(|_| stats.draw_calls > 0) {
+            // glium leaves the query running until the next draw without one, `is_ready` is what ends it here,
+            // before clears, uploads and the buffer swap of the app are timed too.
+            timer.is_ready();
+            self.timer = Some(timer);
+        }
+        if self.streaming != Streaming::Orphan {
+            self.slots[self.slot].fence = glium::SyncFence::new(&self.context).ok();
+        }
+        self.stats = stats;
+        self.dirty = false;
+
+        Ok(())
     }
+}
 
-    fn find_res(&self, id: i32) -> Option<&glium::Texture2d> {
-        if id > 0 && id as usize <= self.tex.len() {
-            Some(&self.tex[(id - 1) as usize])
-        } else {
-            None
+#[derive(Clone, Copy)]
+enum Placement {
+    // Screen-space projection with scissor clipping; scale and surface size.
+    Screen(Vec2, (u32, u32)),
+    // Arbitrary transform with clipping in the fragment shader; whether to depth test.
+    World(bool),
+}
+
+fn program<F: Facade + ?Sized>(facade: &F, outputs_srgb: bool) -> Result<glium::Program, glium::ProgramCreationError> {
+    glium::Program::new(
+        facade,
+        glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: VS,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: FS,
+            transform_feedback_varyings: None,
+            outputs_srgb: outputs_srgb,
+            uses_point_size: false,
+        },
+    )
+}
+
+// Colors reach the blender premultiplied in `AlphaMode::Premultiplied` and for `BlendMode::Multiply`, straight otherwise.
+fn blend(alpha: AlphaMode, mode: BlendMode) -> glium::Blend {
+    use glium::{Blend, BlendingFunction, LinearBlendingFactor};
+
+    let add = |source, destination| BlendingFunction::Addition { source: source, destination: destination };
+    let (src, over) = match alpha {
+        AlphaMode::Straight => (LinearBlendingFactor::SourceAlpha, add(LinearBlendingFactor::SourceAlpha, LinearBlendingFactor::OneMinusSourceAlpha)),
+        AlphaMode::Premultiplied => (LinearBlendingFactor::One, add(LinearBlendingFactor::One, LinearBlendingFactor::OneMinusSourceAlpha)),
+    };
+    let keep = add(LinearBlendingFactor::Zero, LinearBlendingFactor::One);
+    let (color, alpha) = match mode {
+        BlendMode::Alpha => (over, over),
+        BlendMode::Additive => (add(src, LinearBlendingFactor::One), keep),
+        BlendMode::Multiply => (add(LinearBlendingFactor::DestinationColor, LinearBlendingFactor::OneMinusSourceAlpha), keep),
+        BlendMode::Replace => return Blend::default(),
+    };
+    Blend {
+        color: color,
+        alpha: alpha,
+        constant_value: (0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+// A range of the element buffer drawn with one texture and clip, a single nuklear command or several merged ones.
+#[derive(Clone)]
+struct Batch {
+    range: Range<usize>,
+    id: i32,
+    clip: [f32; 4],
+}
+
+// Hashes everything `Context::convert` turns into geometry: the config and the fields of every recorded command.
+// Custom commands draw through a callback whose output cannot be known, so frames with them get no key.
+//
+// Commands are walked with raw pointers straight from nuklear, a `&Command` only covers the header while the
+// variable length commands run past their declared single element array. Fields are hashed one by one, the
+// structs have padding nuklear never writes.
+fn frame_key(ctx: &mut Context, cfg: &ConvertConfig) -> Option<u64> {
+    use nuklear::nuklear_sys::*;
+    use std::ptr::addr_of;
+    use std::slice::from_raw_parts;
+
+    // `Context` only wraps an `nk_context`, which is what nuklear-rust hands to nuklear as well.
+    const _: () = assert!(::std::mem::size_of::<Context>() == ::std::mem::size_of::<nk_context>());
+
+    fn vec2i(v: nk_vec2i) -> (i16, i16) {
+        (v.x, v.y)
+    }
+    fn color(c: nk_color) -> [u8; 4] {
+        [c.r, c.g, c.b, c.a]
+    }
+
+    let mut hasher = DefaultHasher::new();
+    let c: &nk_convert_config = cfg.as_ref();
+    (c.global_alpha.to_bits(), c.line_AA, c.shape_AA, c.circle_segment_count, c.arc_segment_count, c.curve_segment_count).hash(&mut hasher);
+    (unsafe { c.null.texture.id }, c.null.uv.x.to_bits(), c.null.uv.y.to_bits()).hash(&mut hasher);
+    (c.vertex_layout as usize, c.vertex_size, c.vertex_alignment).hash(&mut hasher);
+
+    let ctx = ctx as *mut Context as *mut nk_context;
+    let mut cmd = unsafe { nk__begin(ctx) };
+    while !cmd.is_null() {
+        let kind: CommandType = unsafe { (*cmd).type_ }.into();
+        (kind as u32).hash(&mut hasher);
+        unsafe {
+            match kind {
+                CommandType::Nop => {}
+                CommandType::Scissor => {
+                    let c = &*(cmd as *const nk_command_scissor);
+                    (c.x, c.y, c.w, c.h).hash(&mut hasher);
+                }
+                CommandType::Line => {
+                    let c = &*(cmd as *const nk_command_line);
+                    (c.line_thickness, vec2i(c.begin), vec2i(c.end), color(c.color)).hash(&mut hasher);
+                }
+                CommandType::Curve => {
+                    let c = &*(cmd as *const nk_command_curve);
+                    (c.line_thickness, vec2i(c.begin), vec2i(c.end), vec2i(c.ctrl[0]), vec2i(c.ctrl[1]), color(c.color)).hash(&mut hasher);
+                }
+                CommandType::Rect => {
+                    let c = &*(cmd as *const nk_command_rect);
+                    (c.rounding, c.line_thickness, c.x, c.y, c.w, c.h, color(c.color)).hash(&mut hasher);
+                }
+                CommandType::RectFilled => {
+                    let c = &*(cmd as *const nk_command_rect_filled);
+                    (c.rounding, c.x, c.y, c.w, c.h, color(c.color)).hash(&mut hasher);
+                }
+                CommandType::RectMultiColor => {
+                    let c = &*(cmd as *const nk_command_rect_multi_color);
+                    (c.x, c.y, c.w, c.h, color(c.left), color(c.top), color(c.bottom), color(c.right)).hash(&mut hasher);
+                }
+                CommandType::Circle => {
+                    let c = &*(cmd as *const nk_command_circle);
+                    (c.x, c.y, c.line_thickness, c.w, c.h, color(c.color)).hash(&mut hasher);
+                }
+                CommandType::CircleFilled => {
+                    let c = &*(cmd as *const nk_command_circle_filled);
+                    (c.x, c.y, c.w, c.h, color(c.color)).hash(&mut hasher);
+                }
+                CommandType::Arc => {
+                    let c = &*(cmd as *const nk_command_arc);
+                    (c.cx, c.cy, c.r, c.line_thickness, c.a[0].to_bits(), c.a[1].to_bits(), color(c.color)).hash(&mut hasher);
+                }
+                CommandType::ArcFilled => {
+                    let c = &*(cmd as *const nk_command_arc_filled);
+                    (c.cx, c.cy, c.r, c.a[0].to_bits(), c.a[1].to_bits(), color(c.color)).hash(&mut hasher);
+                }
+                CommandType::Triangle => {
+                    let c = &*(cmd as *const nk_command_triangle);
+                    (c.line_thickness, vec2i(c.a), vec2i(c.b), vec2i(c.c), color(c.color)).hash(&mut hasher);
+                }
+                CommandType::TriangleFilled => {
+                    let c = &*(cmd as *const nk_command_triangle_filled);
+                    (vec2i(c.a), vec2i(c.b), vec2i(c.c), color(c.color)).hash(&mut hasher);
+                }
+                CommandType::Polygon => {
+                    let c = cmd as *const nk_command_polygon;
+                    ((*c).line_thickness, color((*c).color)).hash(&mut hasher);
+                    from_raw_parts(addr_of!((*c).points) as *const nk_vec2i, (*c).point_count as usize).iter().for_each(|p| vec2i(*p).hash(&mut hasher));
+                }
+                CommandType::PolygonFilled => {
+                    let c = cmd as *const nk_command_polygon_filled;
+                    color((*c).color).hash(&mut hasher);
+                    from_raw_parts(addr_of!((*c).points) as *const nk_vec2i, (*c).point_count as usize).iter().for_each(|p| vec2i(*p).hash(&mut hasher));
+                }
+                CommandType::Polyline => {
+                    let c = cmd as *const nk_command_polyline;
+                    ((*c).line_thickness, color((*c).color)).hash(&mut hasher);
+                    from_raw_parts(addr_of!((*c).points) as *const nk_vec2i, (*c).point_count as usize).iter().for_each(|p| vec2i(*p).hash(&mut hasher));
+                }
+                CommandType::Text => {
+                    let c = cmd as *const nk_command_text;
+                    ((*c).font as usize, color((*c).background), color((*c).foreground), (*c).x, (*c).y, (*c).w, (*c).h, (*c).height.to_bits()).hash(&mut hasher);
+                    from_raw_parts(addr_of!((*c).string) as *const u8, (*c).length as usize).hash(&mut hasher);
+                }
+                CommandType::Image => {
+                    let c = &*(cmd as *const nk_command_image);
+                    (c.x, c.y, c.w, c.h, c.img.handle.id, c.img.w, c.img.h, c.img.region, color(c.col)).hash(&mut hasher);
+                }
+                CommandType::Custom => return None,
+            }
         }
+        cmd = unsafe { nk__next(ctx, cmd) };
+    }
+    Some(hasher.finish())
+}
+
+fn clip_rect(clip: &nuklear::Rect) -> [f32; 4] {
+    [clip.x, clip.y, clip.x + clip.w, clip.y + clip.h]
+}
+
+fn clip_quad([x0, y0, x1, y1]: [f32; 4]) -> [Vertex; 4] {
+    let vertex = |x, y| Vertex {
+        pos: Vec2 { x: x, y: y },
+        tex: Vec2 { x: 0.0, y: 0.0 },
+        col: [255; 4],
+    };
+    [vertex(x0, y0), vertex(x1, y0), vertex(x0, y1), vertex(x1, y1)]
+}
+
+fn stencil(test: glium::StencilTest, reference: i32, pass: glium::StencilOperation) -> glium::draw_parameters::Stencil {
+    glium::draw_parameters::Stencil {
+        test_clockwise: test,
+        reference_value_clockwise: reference,
+        depth_pass_operation_clockwise: pass,
+        test_counter_clockwise: test,
+        reference_value_counter_clockwise: reference,
+        depth_pass_operation_counter_clockwise: pass,
+        ..Default::default()
+    }
+}
+
+// Converts a logical nuklear clip rectangle into a framebuffer scissor box, rounding each edge to the nearest pixel.
+fn scissor([x0, y0, x1, y1]: [f32; 4], scale: Vec2, (ww, hh): (u32, u32)) -> glium::Rect {
+    let edge = |v: f32, max: u32| v.round().max(0.0).min(max as f32) as u32;
+
+    let left = edge(x0 * scale.x, ww);
+    let right = edge(x1 * scale.x, ww);
+    let top = edge(y0 * scale.y, hh);
+    let bottom = edge(y1 * scale.y, hh);
+
+    glium::Rect {
+        left: left,
+        bottom: hh - bottom,
+        width: right.saturating_sub(left),
+        height: bottom.saturating_sub(top),
     }
 }
+
+// One vertex and element buffer pair of the streaming ring, with the fence of the last frame that drew from it.
+struct Slot {
+    vbo: glium::VertexBuffer<Vertex>,
+    ebo: glium::IndexBuffer<u16>,
+    fence: Option<glium::SyncFence>,
+}
+
+fn vertex_buffer<F: Facade + ?Sized>(facade: &F, vbo_size: usize, persistent: bool) -> Result<glium::VertexBuffer<Vertex>, DrawerError> {
+    Ok(if persistent {
+        glium::VertexBuffer::empty_persistent(facade, vbo_size)?
+    } else {
+        glium::VertexBuffer::empty_dynamic(facade, vbo_size)?
+    })
+}
+
+fn element_buffer<F: Facade + ?Sized>(facade: &F, ebo_size: usize, persistent: bool) -> Result<glium::IndexBuffer<u16>, DrawerError> {
+    let prim = glium::index::PrimitiveType::TrianglesList;
+    Ok(if persistent {
+        glium::IndexBuffer::empty_persistent(facade, prim, ebo_size)?
+    } else {
+        glium::IndexBuffer::empty_dynamic(facade, prim, ebo_size)?
+    })
+}
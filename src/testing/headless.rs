@@ -0,0 +1,124 @@
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+
+use glutin_egl_sys::egl;
+use glutin_egl_sys::egl::types::{EGLContext, EGLDisplay, EGLint};
+use libloading::Library;
+
+use super::HarnessError;
+
+const PLATFORM_SURFACELESS_MESA: egl::types::EGLenum = 0x31DD;
+
+struct EglBackend {
+    egl: egl::Egl,
+    display: EGLDisplay,
+    context: EGLContext,
+    size: (u32, u32),
+    gl: Option<Library>,
+    _lib: Library,
+}
+
+unsafe impl glium::backend::Backend for EglBackend {
+    fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
+        Ok(())
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        let name = CString::new(symbol).unwrap();
+        let addr = self.egl.GetProcAddress(name.as_ptr()) as *const c_void;
+        if !addr.is_null() {
+            return addr;
+        }
+        // Mesa before EGL 1.5 does not hand out core entry points through eglGetProcAddress.
+        self.gl.as_ref().and_then(|gl| gl.get::<*const c_void>(name.as_bytes_with_nul()).ok()).map(|s| *s).unwrap_or(ptr::null())
+    }
+
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn is_current(&self) -> bool {
+        unsafe { self.egl.GetCurrentContext() == self.context }
+    }
+
+    unsafe fn make_current(&self) {
+        self.egl.MakeCurrent(self.display, egl::NO_SURFACE, egl::NO_SURFACE, self.context);
+    }
+}
+
+impl Drop for EglBackend {
+    fn drop(&mut self) {
+        unsafe {
+            self.egl.MakeCurrent(self.display, egl::NO_SURFACE, egl::NO_SURFACE, egl::NO_CONTEXT);
+            self.egl.DestroyContext(self.display, self.context);
+        }
+    }
+}
+
+/// Creates an OpenGL 3.3 core context without a window or surface, rendering only into framebuffer objects.
+///
+/// Relies on `EGL_MESA_platform_surfaceless` (or a default EGL display that allows surfaceless contexts),
+/// which Mesa provides with its llvmpipe software rasterizer, so no GPU or display server is needed.
+pub fn context(width: u32, height: u32) -> Result<Rc<glium::backend::Context>, HarnessError> {
+    unsafe {
+        let lib = Library::new("libEGL.so.1").or_else(|_| Library::new("libEGL.so")).map_err(|_| HarnessError::Egl("libEGL could not be loaded"))?;
+        let gl = Library::new("libGL.so.1").ok();
+        let egl = egl::Egl::load_with(|symbol| {
+            let name = CString::new(symbol).unwrap();
+            lib.get::<*const c_void>(name.as_bytes_with_nul()).map(|s| *s).unwrap_or(ptr::null())
+        });
+
+        let mut display = egl::NO_DISPLAY;
+        if egl.GetPlatformDisplay.is_loaded() {
+            display = egl.GetPlatformDisplay(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY as *mut c_void, ptr::null());
+        }
+        if display == egl::NO_DISPLAY {
+            display = egl.GetDisplay(egl::DEFAULT_DISPLAY as *mut c_void);
+        }
+        if display == egl::NO_DISPLAY {
+            return Err(HarnessError::Egl("no EGL display available"));
+        }
+
+        let (mut major, mut minor) = (0, 0);
+        if egl.Initialize(display, &mut major, &mut minor) == egl::FALSE {
+            return Err(HarnessError::Egl("eglInitialize failed"));
+        }
+        if egl.BindAPI(egl::OPENGL_API) == egl::FALSE {
+            return Err(HarnessError::Egl("desktop OpenGL is not supported by this EGL"));
+        }
+
+        // The surfaceless platform only exposes pbuffer configs; without any, rely on EGL_KHR_no_config_context.
+        let config_attribs = [egl::SURFACE_TYPE as EGLint, egl::PBUFFER_BIT as EGLint, egl::RENDERABLE_TYPE as EGLint, egl::OPENGL_BIT as EGLint, egl::NONE as EGLint];
+        let mut config = ptr::null();
+        let mut configs = 0;
+        if egl.ChooseConfig(display, config_attribs.as_ptr(), &mut config, 1, &mut configs) == egl::FALSE || configs == 0 {
+            config = ptr::null();
+        }
+
+        let context_attribs = [
+            egl::CONTEXT_MAJOR_VERSION as EGLint,
+            3,
+            egl::CONTEXT_MINOR_VERSION as EGLint,
+            3,
+            egl::CONTEXT_OPENGL_PROFILE_MASK as EGLint,
+            egl::CONTEXT_OPENGL_CORE_PROFILE_BIT as EGLint,
+            egl::NONE as EGLint,
+        ];
+        let context = egl.CreateContext(display, config, egl::NO_CONTEXT, context_attribs.as_ptr());
+        if context == egl::NO_CONTEXT {
+            return Err(HarnessError::Egl("OpenGL 3.3 core context creation failed"));
+        }
+
+        let backend = EglBackend {
+            egl: egl,
+            display: display,
+            context: context,
+            size: (width, height),
+            gl: gl,
+            _lib: lib,
+        };
+        Ok(glium::backend::Context::new(backend, true, Default::default())?)
+    }
+}
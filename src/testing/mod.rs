@@ -0,0 +1,443 @@
+//! Headless rendering and golden-image comparison for UI regression tests.
+//!
+//! Enabled with the `testing` feature. Rendering goes through Mesa's EGL surfaceless platform,
+//! so tests run on CI machines without a GPU or display server.
+//!
+//! ```no_run
+//! use nuklear_backend_glium::testing::{assert_golden, Harness, Tolerance};
+//!
+//! let font = std::fs::read("tests/fonts/ProggyClean.ttf").unwrap();
+//! let mut harness = Harness::new(320, 240, &font, 13.0).unwrap();
+//! let image = harness
+//!     .frame(|ctx| {
+//!         if ctx.begin(nuklear::nk_string!("Demo"), nuklear::Rect { x: 10., y: 10., w: 200., h: 150. }, nuklear::PanelFlags::Title as nuklear::Flags) {
+//!             ctx.layout_row_dynamic(30., 1);
+//!             ctx.button_text("Button");
+//!         }
+//!         ctx.end();
+//!     })
+//!     .unwrap();
+//! assert_golden(&image, "tests/golden/demo.png", Tolerance::default());
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use glium::Surface;
+
+use nuklear::{Allocator, Buffer, Context, ConvertConfig, Vec2};
+
+use crate::{Drawer, DrawerError, FontManager};
+
+mod headless;
+mod png;
+
+pub use self::headless::context;
+
+/// Environment variable that makes `assert_golden` write golden images instead of comparing against them.
+pub const UPDATE_GOLDEN_ENV: &str = "NUKLEAR_UPDATE_GOLDEN";
+
+/// Environment variable for skipping tests that need a GL context on machines known to lack one.
+///
+/// `Harness::new` does not read it, tests check it when creation fails so that CI without Mesa EGL fails instead of passing empty.
+pub const SKIP_GL_TESTS_ENV: &str = "NUKLEAR_SKIP_GL_TESTS";
+
+#[derive(Debug)]
+pub enum HarnessError {
+    Egl(&'static str),
+    Context(glium::IncompatibleOpenGl),
+    Framebuffer(glium::framebuffer::ValidationError),
+    RenderBuffer(glium::framebuffer::RenderBufferCreationError),
+    Drawer(DrawerError),
+    Png(&'static str),
+    Io(io::Error),
+}
+
+impl fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HarnessError::Egl(msg) => write!(f, "Headless context creation failed: {}", msg),
+            HarnessError::Context(ref e) => write!(f, "Headless context creation failed: {}", e),
+            HarnessError::Framebuffer(ref e) => write!(f, "Offscreen framebuffer creation failed: {:?}", e),
+            HarnessError::RenderBuffer(ref e) => write!(f, "Depth-stencil buffer creation failed: {}", e),
+            HarnessError::Drawer(ref e) => write!(f, "{}", e),
+            HarnessError::Png(msg) => write!(f, "PNG decoding failed: {}", msg),
+            HarnessError::Io(ref e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for HarnessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            HarnessError::Context(ref e) => Some(e),
+            HarnessError::RenderBuffer(ref e) => Some(e),
+            HarnessError::Drawer(ref e) => Some(e),
+            HarnessError::Io(ref e) => Some(e),
+            HarnessError::Egl(_) | HarnessError::Framebuffer(_) | HarnessError::Png(_) => None,
+        }
+    }
+}
+
+impl From<glium::IncompatibleOpenGl> for HarnessError {
+    fn from(e: glium::IncompatibleOpenGl) -> HarnessError {
+        HarnessError::Context(e)
+    }
+}
+
+impl From<glium::framebuffer::ValidationError> for HarnessError {
+    fn from(e: glium::framebuffer::ValidationError) -> HarnessError {
+        HarnessError::Framebuffer(e)
+    }
+}
+
+impl From<glium::framebuffer::RenderBufferCreationError> for HarnessError {
+    fn from(e: glium::framebuffer::RenderBufferCreationError) -> HarnessError {
+        HarnessError::RenderBuffer(e)
+    }
+}
+
+impl From<glium::texture::TextureCreationError> for HarnessError {
+    fn from(e: glium::texture::TextureCreationError) -> HarnessError {
+        HarnessError::Drawer(e.into())
+    }
+}
+
+impl From<DrawerError> for HarnessError {
+    fn from(e: DrawerError) -> HarnessError {
+        HarnessError::Drawer(e)
+    }
+}
+
+impl From<io::Error> for HarnessError {
+    fn from(e: io::Error) -> HarnessError {
+        HarnessError::Io(e)
+    }
+}
+
+/// 8-bit RGBA pixels, rows stored top to bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn decode_png(data: &[u8]) -> Result<Snapshot, HarnessError> {
+        let (width, height, pixels) = png::decode(data)?;
+        Ok(Snapshot { width: width, height: height, pixels: pixels })
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        png::encode(self.width, self.height, &self.pixels)
+    }
+
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Snapshot, HarnessError> {
+        Snapshot::decode_png(&std::fs::read(path)?)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), HarnessError> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(std::fs::write(path, self.encode_png())?)
+    }
+
+    /// Compares two images channel by channel. Images of different size differ in every pixel.
+    pub fn diff(&self, other: &Snapshot, tolerance: Tolerance) -> Diff {
+        if self.width != other.width || self.height != other.height {
+            return Diff {
+                mismatched: self.width.max(other.width) as usize * self.height.max(other.height) as usize,
+                max_delta: 255,
+                tolerance: tolerance,
+            };
+        }
+
+        let mut diff = Diff {
+            mismatched: 0,
+            max_delta: 0,
+            tolerance: tolerance,
+        };
+        for (a, b) in self.pixels.chunks(4).zip(other.pixels.chunks(4)) {
+            let delta = a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+            diff.max_delta = diff.max_delta.max(delta);
+            if delta > tolerance.channel {
+                diff.mismatched += 1;
+            }
+        }
+        diff
+    }
+}
+
+/// How far a rendered image may stray from its golden image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tolerance {
+    /// Largest per-channel difference for a pixel to still count as matching.
+    pub channel: u8,
+    /// Number of pixels allowed to exceed `channel`.
+    pub pixels: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance { channel: 2, pixels: 0 }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Diff {
+    pub mismatched: usize,
+    pub max_delta: u8,
+    pub tolerance: Tolerance,
+}
+
+impl Diff {
+    pub fn passed(&self) -> bool {
+        self.mismatched <= self.tolerance.pixels
+    }
+}
+
+/// Compares `image` with the PNG at `path` and panics when they differ beyond `tolerance`.
+///
+/// Golden images are only written, missing ones included, when `NUKLEAR_UPDATE_GOLDEN` is set.
+/// On mismatch the rendered image is saved next to the golden one with an `.actual.png` suffix.
+pub fn assert_golden<P: AsRef<Path>>(image: &Snapshot, path: P, tolerance: Tolerance) {
+    check_golden(image, path.as_ref(), tolerance, std::env::var_os(UPDATE_GOLDEN_ENV).is_some());
+}
+
+fn check_golden(image: &Snapshot, path: &Path, tolerance: Tolerance, update: bool) {
+    if update {
+        image.save_png(path).unwrap_or_else(|e| panic!("cannot write golden image {}: {}", path.display(), e));
+        return;
+    }
+    if !path.exists() {
+        panic!("golden image {} does not exist, run with {} set to create it", path.display(), UPDATE_GOLDEN_ENV);
+    }
+
+    let golden = Snapshot::load_png(path).unwrap_or_else(|e| panic!("cannot read golden image {}: {}", path.display(), e));
+    let diff = image.diff(&golden, tolerance);
+    if !diff.passed() {
+        let actual = path.with_extension("actual.png");
+        let _ = image.save_png(&actual);
+        panic!(
+            "{} differs from the rendered image: {} pixels off by up to {} (tolerance {:?}), {}x{} rendered vs {}x{} golden, output saved to {}",
+            path.display(),
+            diff.mismatched,
+            diff.max_delta,
+            tolerance,
+            image.width,
+            image.height,
+            golden.width,
+            golden.height,
+            actual.display()
+        );
+    }
+}
+
+/// A headless GL context, drawer, font and nuklear context wired together for rendering UI frames offscreen.
+pub struct Harness {
+    ctx: Context,
+    cfg: ConvertConfig,
+    drawer: Drawer,
+    target: glium::Texture2d,
+    depth_stencil: glium::framebuffer::DepthStencilRenderBuffer,
+    clear_color: (f32, f32, f32, f32),
+    scale: Vec2,
+    input_done: bool,
+    _fonts: FontManager,
+    _allocator: Allocator,
+    facade: Rc<glium::backend::Context>,
+}
+
+impl Harness {
+    /// Creates a `width` x `height` pixel render target and bakes the TrueType font in `font` at `font_size`.
+    pub fn new(width: u32, height: u32, font: &[u8], font_size: f32) -> Result<Harness, HarnessError> {
+        let facade = context(width, height)?;
+        let mut allocator = Allocator::new_vec();
+        let mut drawer = Drawer::try_new(&facade, 16, 4096, 4096, Buffer::with_size(&mut allocator, 64 * 1024))?;
+
+        let mut fonts = FontManager::new(&allocator);
+        let font = fonts.add_font(font, font_size);
+        let cfg = fonts.bake(&mut drawer, &facade, 1.0)?;
+        let ctx = Context::new(&mut allocator, fonts.font(font).unwrap().handle());
+        let target = glium::Texture2d::empty(&facade, width, height)?;
+        // Lets frames use `ClipMode::Stencil` and depth tested transforms.
+        let depth_stencil = glium::framebuffer::DepthStencilRenderBuffer::new(&facade, glium::texture::DepthStencilFormat::I24I8, width, height)?;
+
+        Ok(Harness {
+            ctx: ctx,
+            cfg: cfg,
+            drawer: drawer,
+            target: target,
+            depth_stencil: depth_stencil,
+            clear_color: (0.0, 0.0, 0.0, 1.0),
+            scale: Vec2 { x: 1.0, y: 1.0 },
+            input_done: false,
+            _fonts: fonts,
+            _allocator: allocator,
+            facade: facade,
+        })
+    }
+
+    pub fn facade(&self) -> &Rc<glium::backend::Context> {
+        &self.facade
+    }
+
+    pub fn drawer(&mut self) -> &mut Drawer {
+        &mut self.drawer
+    }
+
+    pub fn convert_config(&mut self) -> &mut ConvertConfig {
+        &mut self.cfg
+    }
+
+    pub fn set_clear_color(&mut self, color: (f32, f32, f32, f32)) {
+        self.clear_color = color;
+    }
+
+    /// Surface pixels per nuklear logical pixel, passed on to `Drawer::draw`.
+    pub fn set_scale(&mut self, scale: Vec2) {
+        self.scale = scale;
+    }
+
+    /// Feeds scripted input (`input_motion`, `input_button`, ...) to the context for the next frame.
+    pub fn input<F: FnOnce(&mut Context)>(&mut self, input: F) {
+        if !self.input_done {
+            self.ctx.input_begin();
+        }
+        input(&mut self.ctx);
+        self.input_done = true;
+    }
+
+    /// Runs `ui` as one nuklear frame, draws it offscreen and reads back the pixels.
+    pub fn frame<F: FnOnce(&mut Context)>(&mut self, ui: F) -> Result<Snapshot, HarnessError> {
+        if !self.input_done {
+            self.ctx.input_begin();
+        }
+        self.ctx.input_end();
+        self.input_done = false;
+
+        ui(&mut self.ctx);
+
+        {
+            let mut fb = glium::framebuffer::SimpleFrameBuffer::with_depth_stencil_buffer(&self.facade, &self.target, &self.depth_stencil)?;
+            fb.clear_all(self.clear_color, 1.0, 0);
+            self.drawer.try_draw(&mut self.ctx, &mut self.cfg, &mut fb, self.scale)?;
+        }
+        self.ctx.clear();
+
+        Ok(self.read())
+    }
+
+    fn read(&self) -> Snapshot {
+        let (width, height) = self.target.dimensions();
+        let rows: Vec<Vec<(u8, u8, u8, u8)>> = self.target.read();
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in rows.iter().rev() {
+            for &(r, g, b, a) in row {
+                pixels.extend_from_slice(&[r, g, b, a]);
+            }
+        }
+        Snapshot { width: width, height: height, pixels: pixels }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn snapshot(width: u32, height: u32, value: u8) -> Snapshot {
+        Snapshot {
+            width: width,
+            height: height,
+            pixels: vec![value; width as usize * height as usize * 4],
+        }
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nuklear-backend-glium-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn diff_within_tolerance() {
+        let a = snapshot(4, 4, 100);
+        let mut b = a.clone();
+        b.pixels[0] = 102;
+        let diff = a.diff(&b, Tolerance::default());
+        assert_eq!((diff.mismatched, diff.max_delta), (0, 2));
+        assert!(diff.passed());
+    }
+
+    #[test]
+    fn diff_counts_pixels_not_channels() {
+        let a = snapshot(4, 4, 100);
+        let mut b = a.clone();
+        b.pixels[0] = 110;
+        b.pixels[1] = 90;
+        b.pixels[8] = 120;
+        let diff = a.diff(&b, Tolerance::default());
+        assert_eq!((diff.mismatched, diff.max_delta), (2, 20));
+        assert!(!diff.passed());
+        assert!(a.diff(&b, Tolerance { channel: 2, pixels: 2 }).passed());
+        assert!(a.diff(&b, Tolerance { channel: 20, pixels: 0 }).passed());
+    }
+
+    #[test]
+    fn diff_of_different_sizes_fails() {
+        let diff = snapshot(4, 4, 0).diff(&snapshot(4, 5, 0), Tolerance { channel: 255, pixels: 19 });
+        assert_eq!((diff.mismatched, diff.max_delta), (20, 255));
+        assert!(!diff.passed());
+    }
+
+    #[test]
+    fn golden_is_written_then_compared() {
+        let dir = scratch_dir("golden");
+        let path = dir.join("nested").join("image.png");
+        let image = snapshot(3, 2, 50);
+
+        check_golden(&image, &path, Tolerance::default(), true);
+        assert_eq!(Snapshot::load_png(&path).unwrap(), image);
+
+        let mut close = image.clone();
+        close.pixels[5] = 52;
+        check_golden(&close, &path, Tolerance::default(), false);
+        assert!(!path.with_extension("actual.png").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_golden_panics() {
+        let dir = scratch_dir("missing");
+        let path = dir.join("image.png");
+
+        let result = std::panic::catch_unwind(|| check_golden(&snapshot(3, 2, 50), &path, Tolerance::default(), false));
+        assert!(result.is_err());
+        assert!(!path.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn golden_mismatch_panics_and_keeps_output() {
+        let dir = scratch_dir("mismatch");
+        let path = dir.join("image.png");
+        snapshot(3, 2, 50).save_png(&path).unwrap();
+
+        let different = snapshot(3, 2, 200);
+        let result = std::panic::catch_unwind(|| check_golden(&different, &path, Tolerance::default(), false));
+        assert!(result.is_err());
+        assert_eq!(Snapshot::load_png(path.with_extension("actual.png")).unwrap(), different);
+        assert_eq!(Snapshot::load_png(&path).unwrap(), snapshot(3, 2, 50));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
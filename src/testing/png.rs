@@ -0,0 +1,202 @@
+// Minimal PNG codec for golden images: writes 8-bit RGBA, reads 8-bit non-interlaced grey, RGB and RGBA (with or without alpha).
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+use super::HarnessError;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+pub(crate) fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in rgba.chunks(stride) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut out = SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &compress_to_vec_zlib(&raw, 6));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+pub(crate) fn decode(data: &[u8]) -> Result<(u32, u32, Vec<u8>), HarnessError> {
+    if data.len() < SIGNATURE.len() || data[..SIGNATURE.len()] != SIGNATURE {
+        return Err(HarnessError::Png("not a PNG file"));
+    }
+
+    let mut pos = SIGNATURE.len();
+    let mut header = None;
+    let mut idat = Vec::new();
+    while pos + 12 <= data.len() {
+        let len = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data.get(pos + 8..pos + 8 + len).ok_or(HarnessError::Png("truncated chunk"))?;
+        match kind {
+            b"IHDR" if len == 13 => header = Some(body),
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += len + 12;
+    }
+
+    let header = header.ok_or(HarnessError::Png("missing IHDR"))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let channels = match (header[8], header[9]) {
+        (8, 0) => 1,
+        (8, 2) => 3,
+        (8, 4) => 2,
+        (8, 6) => 4,
+        _ => return Err(HarnessError::Png("only 8-bit grey, RGB and RGBA images are supported")),
+    };
+    if header[12] != 0 {
+        return Err(HarnessError::Png("interlaced images are not supported"));
+    }
+
+    let raw = decompress_to_vec_zlib(&idat).map_err(|_| HarnessError::Png("corrupt image data"))?;
+    let stride = width as usize * channels;
+    if raw.len() < (stride + 1) * height as usize {
+        return Err(HarnessError::Png("truncated image data"));
+    }
+
+    let mut pixels = vec![0u8; stride * height as usize];
+    let mut prev = vec![0u8; stride];
+    for (y, line) in raw.chunks(stride + 1).take(height as usize).enumerate() {
+        let row = &mut pixels[y * stride..(y + 1) * stride];
+        row.copy_from_slice(&line[1..]);
+        unfilter(line[0], row, &prev, channels)?;
+        prev.copy_from_slice(row);
+    }
+
+    let rgba = match channels {
+        4 => pixels,
+        3 => pixels.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        2 => pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        _ => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+    };
+    Ok((width, height, rgba))
+}
+
+fn unfilter(filter: u8, row: &mut [u8], prev: &[u8], bpp: usize) -> Result<(), HarnessError> {
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prev[i];
+        let c = if i >= bpp { prev[i - bpp] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            4 => paeth(a, b, c),
+            _ => return Err(HarnessError::Png("unknown filter type")),
+        };
+        row[i] = row[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A PNG of the given color type whose rows already carry their filter byte.
+    fn png(width: u32, height: u32, color_type: u8, raw: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+        let mut out = SIGNATURE.to_vec();
+        chunk(&mut out, b"IHDR", &header);
+        chunk(&mut out, b"IDAT", &compress_to_vec_zlib(raw, 6));
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let rgba: Vec<u8> = (0..3 * 2 * 4).map(|i| (i * 11) as u8).collect();
+        let (width, height, pixels) = decode(&encode(3, 2, &rgba)).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(pixels, rgba);
+    }
+
+    #[test]
+    fn crc_of_iend() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn decodes_every_filter() {
+        // Grey, 2x5, one row per filter type; each row decodes to [10 * y + 1, 10 * y + 3].
+        let raw = [
+            0, 1, 3, // none
+            1, 11, 2, // sub: 11, 11 + 2
+            2, 10, 10, // up: 11 + 10, 13 + 10
+            3, 21, 6, // average: 21 / 2 + 21, (31 + 23) / 2 + 6
+            4, 10, 2, // paeth: up for the first byte, left for the second
+        ];
+        let (_, _, pixels) = decode(&png(2, 5, 0, &raw)).unwrap();
+        let grey: Vec<u8> = pixels.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(grey, [1, 3, 11, 13, 21, 23, 31, 33, 41, 43]);
+        assert!(pixels.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+    }
+
+    #[test]
+    fn expands_rgb_and_grey_alpha() {
+        let (_, _, rgb) = decode(&png(1, 1, 2, &[0, 1, 2, 3])).unwrap();
+        assert_eq!(rgb, [1, 2, 3, 255]);
+        let (_, _, grey_alpha) = decode(&png(1, 1, 4, &[0, 7, 9])).unwrap();
+        assert_eq!(grey_alpha, [7, 7, 7, 9]);
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        assert!(decode(b"GIF89a").is_err());
+        assert!(decode(&png(2, 2, 0, &[0, 1, 2])).is_err());
+        assert!(decode(&png(1, 1, 0, &[5, 1])).is_err());
+        let encoded = encode(1, 1, &[0; 4]);
+        assert!(decode(&encoded[..encoded.len() - 20]).is_err());
+    }
+}
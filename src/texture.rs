@@ -0,0 +1,350 @@
+use std::borrow::Cow;
+use std::rc::Rc;
+
+use glium::backend::Facade;
+use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, SrgbFormat, SrgbTexture2d, UncompressedFloatFormat};
+use glium::uniforms::{MinifySamplerFilter, SamplerBehavior, UniformValue};
+use glium::Texture2d;
+
+use nuklear::Handle;
+
+use crate::DrawerError;
+
+const SLOT_BITS: u32 = 20;
+const SLOT_MASK: i32 = (1 << SLOT_BITS) - 1;
+const GENERATION_MASK: u32 = 0x7FF;
+
+/// Layout of the pixel data handed to `Drawer::add_texture_with`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    #[default]
+    Rgba,
+    Rgb,
+    Bgra,
+    /// One byte per pixel used as coverage, sampled as white with that alpha. Fits the `Alpha8` nuklear font atlas.
+    Alpha,
+    /// One byte per pixel sampled as an opaque grey.
+    Luminance,
+    SrgbRgba,
+    SrgbRgb,
+}
+
+impl ImageFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            ImageFormat::Rgba | ImageFormat::Bgra | ImageFormat::SrgbRgba => 4,
+            ImageFormat::Rgb | ImageFormat::SrgbRgb => 3,
+            ImageFormat::Alpha | ImageFormat::Luminance => 1,
+        }
+    }
+
+    fn client_format(self) -> ClientFormat {
+        match self.bytes_per_pixel() {
+            4 => ClientFormat::U8U8U8U8,
+            3 => ClientFormat::U8U8U8,
+            _ => ClientFormat::U8,
+        }
+    }
+
+    // Matches the `Swizzle` branches of the fragment shader.
+    fn swizzle(self) -> i32 {
+        match self {
+            ImageFormat::Alpha => 1,
+            ImageFormat::Luminance => 2,
+            ImageFormat::Bgra => 3,
+            _ => 0,
+        }
+    }
+}
+
+/// How commands using a texture are combined with what is already in the target.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Regular "over" compositing.
+    #[default]
+    Alpha,
+    /// Adds the color weighted by its alpha, e.g. for glows. Leaves the target alpha untouched.
+    Additive,
+    /// Multiplies the target by the color, fading to no change as alpha goes to zero.
+    Multiply,
+    /// Overwrites the target, alpha included.
+    Replace,
+}
+
+/// Settings stored with each texture registered through `Drawer::add_texture_with`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureOptions {
+    pub format: ImageFormat,
+    /// Filtering, wrapping and anisotropy applied whenever a command samples the texture.
+    pub sampler: SamplerBehavior,
+    /// Generate a mipmap chain on upload. Without one, mipmapped minify filters fall back to their base level.
+    pub mipmaps: bool,
+    pub blend: BlendMode,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            format: ImageFormat::default(),
+            sampler: SamplerBehavior::default(),
+            mipmaps: true,
+            blend: BlendMode::default(),
+        }
+    }
+}
+
+impl TextureOptions {
+    fn sampler(&self) -> SamplerBehavior {
+        if self.mipmaps {
+            return self.sampler;
+        }
+        let minify_filter = match self.sampler.minify_filter {
+            MinifySamplerFilter::Nearest | MinifySamplerFilter::NearestMipmapNearest | MinifySamplerFilter::NearestMipmapLinear => MinifySamplerFilter::Nearest,
+            _ => MinifySamplerFilter::Linear,
+        };
+        SamplerBehavior { minify_filter: minify_filter, ..self.sampler }
+    }
+}
+
+pub(crate) enum TextureData {
+    Linear(Texture2d),
+    Srgb(SrgbTexture2d),
+    Shared(Rc<Texture2d>),
+}
+
+pub(crate) struct Texture {
+    data: TextureData,
+    options: TextureOptions,
+}
+
+impl Texture {
+    /// With `srgb`, color formats are stored as `SrgbTexture2d` like the explicit `Srgb*` formats.
+    pub(crate) fn new<F: Facade + ?Sized>(facade: &F, image: &[u8], width: u32, height: u32, options: TextureOptions, srgb: bool) -> Result<Texture, DrawerError> {
+        let format = options.format;
+        check_size(image, width, height, format)?;
+
+        let raw = raw_image(image, width, height, format);
+        let mipmaps = if options.mipmaps { MipmapsOption::AutoGeneratedMipmaps } else { MipmapsOption::NoMipmap };
+        let data = match format {
+            ImageFormat::SrgbRgba => TextureData::Srgb(SrgbTexture2d::with_format(facade, raw, SrgbFormat::U8U8U8U8, mipmaps)?),
+            ImageFormat::SrgbRgb => TextureData::Srgb(SrgbTexture2d::with_format(facade, raw, SrgbFormat::U8U8U8, mipmaps)?),
+            ImageFormat::Rgba | ImageFormat::Bgra if srgb => TextureData::Srgb(SrgbTexture2d::with_format(facade, raw, SrgbFormat::U8U8U8U8, mipmaps)?),
+            ImageFormat::Rgb if srgb => TextureData::Srgb(SrgbTexture2d::with_format(facade, raw, SrgbFormat::U8U8U8, mipmaps)?),
+            ImageFormat::Rgb => TextureData::Linear(Texture2d::with_format(facade, raw, UncompressedFloatFormat::U8U8U8, mipmaps)?),
+            ImageFormat::Alpha | ImageFormat::Luminance => TextureData::Linear(Texture2d::with_format(facade, raw, UncompressedFloatFormat::U8, mipmaps)?),
+            ImageFormat::Rgba | ImageFormat::Bgra => TextureData::Linear(Texture2d::with_format(facade, raw, UncompressedFloatFormat::U8U8U8U8, mipmaps)?),
+        };
+
+        Ok(Texture { data: data, options: options })
+    }
+
+    pub(crate) fn shared(texture: Rc<Texture2d>) -> Texture {
+        Texture {
+            data: TextureData::Shared(texture),
+            options: TextureOptions::default(),
+        }
+    }
+
+    pub(crate) fn options(&self) -> &TextureOptions {
+        &self.options
+    }
+
+    pub(crate) fn is_srgb(&self) -> bool {
+        matches!(self.data, TextureData::Srgb(_))
+    }
+
+    pub(crate) fn dimensions(&self) -> (u32, u32) {
+        match self.data {
+            TextureData::Linear(ref t) => t.dimensions(),
+            TextureData::Srgb(ref t) => t.dimensions(),
+            TextureData::Shared(ref t) => t.dimensions(),
+        }
+    }
+
+    pub(crate) fn write(&self, x: u32, y: u32, width: u32, height: u32, data: &[u8]) -> Result<(), DrawerError> {
+        let format = self.options.format;
+        check_size(data, width, height, format)?;
+
+        let (tw, th) = self.dimensions();
+        if x.checked_add(width).is_none_or(|r| r > tw) || y.checked_add(height).is_none_or(|b| b > th) {
+            return Err(DrawerError::RegionOutOfBounds);
+        }
+
+        let rect = glium::Rect {
+            left: x,
+            bottom: y,
+            width: width,
+            height: height,
+        };
+        let raw = raw_image(data, width, height, format);
+        match self.data {
+            TextureData::Linear(ref t) => t.write(rect, raw),
+            TextureData::Srgb(ref t) => t.write(rect, raw),
+            TextureData::Shared(ref t) => t.write(rect, raw),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn uniform(&self) -> UniformValue<'_> {
+        let sampler = self.options.sampler();
+        match self.data {
+            TextureData::Linear(ref t) => UniformValue::Texture2d(t, Some(sampler)),
+            TextureData::Srgb(ref t) => UniformValue::SrgbTexture2d(t, Some(sampler)),
+            TextureData::Shared(ref t) => UniformValue::Texture2d(t, Some(sampler)),
+        }
+    }
+
+    pub(crate) fn swizzle(&self) -> i32 {
+        self.options.format.swizzle()
+    }
+}
+
+/// Textures owned by the caller and lent to the drawer for a single `Drawer::draw_with` call.
+///
+/// Handles returned by `add` are only valid for the draw that receives this registry.
+#[derive(Default)]
+pub struct FrameTextures<'a> {
+    textures: Vec<&'a Texture2d>,
+}
+
+impl<'a> FrameTextures<'a> {
+    pub fn new() -> FrameTextures<'a> {
+        FrameTextures::default()
+    }
+
+    pub fn add(&mut self, texture: &'a Texture2d) -> Handle {
+        self.textures.push(texture);
+        Handle::from_id(-(self.textures.len() as i32))
+    }
+
+    pub fn clear(&mut self) {
+        self.textures.clear();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum TextureRef<'a> {
+    Registered(&'a Texture),
+    Borrowed(&'a Texture2d),
+}
+
+impl<'a> TextureRef<'a> {
+    pub(crate) fn uniform(self) -> UniformValue<'a> {
+        match self {
+            TextureRef::Registered(t) => t.uniform(),
+            TextureRef::Borrowed(t) => UniformValue::Texture2d(t, Some(SamplerBehavior::default())),
+        }
+    }
+
+    pub(crate) fn blend_mode(self) -> BlendMode {
+        match self {
+            TextureRef::Registered(t) => t.options.blend,
+            TextureRef::Borrowed(_) => BlendMode::Alpha,
+        }
+    }
+
+    // Matches the `TexelConvert` branches of the fragment shader. Shared and borrowed `Texture2d`s are taken as linear data.
+    pub(crate) fn texel_convert(self, linear_pipeline: bool) -> i32 {
+        match self {
+            TextureRef::Registered(t) => match t.data {
+                TextureData::Linear(_) if linear_pipeline => 1,
+                TextureData::Srgb(_) if !linear_pipeline => 2,
+                _ => 0,
+            },
+            TextureRef::Borrowed(_) => 0,
+        }
+    }
+
+    pub(crate) fn swizzle(self) -> i32 {
+        match self {
+            TextureRef::Registered(t) => t.swizzle(),
+            TextureRef::Borrowed(_) => 0,
+        }
+    }
+}
+
+pub(crate) struct TextureSlot {
+    pub(crate) tex: Option<Texture>,
+    pub(crate) generation: u32,
+}
+
+impl TextureSlot {
+    // Returns `false` once the generation is used up. Wrapping it would let stale handles resolve again,
+    // so such a slot stays empty for good.
+    pub(crate) fn release(&mut self) -> bool {
+        self.tex = None;
+        if self.generation == GENERATION_MASK {
+            return false;
+        }
+        self.generation += 1;
+        true
+    }
+}
+
+pub(crate) fn handle_id(mut handle: Handle) -> i32 {
+    handle.id().unwrap_or(0)
+}
+
+// Slot ids start at 1 so that a zeroed handle never resolves.
+pub(crate) const MAX_SLOTS: usize = SLOT_MASK as usize;
+
+pub(crate) fn slot_id(slot: usize, generation: u32) -> i32 {
+    ((generation << SLOT_BITS) as i32) | (slot as i32 + 1)
+}
+
+pub(crate) fn find_slot(tex: &[TextureSlot], id: i32) -> Option<usize> {
+    if id <= 0 || id & SLOT_MASK == 0 {
+        return None;
+    }
+    let slot = ((id & SLOT_MASK) - 1) as usize;
+    match tex.get(slot) {
+        Some(s) if s.tex.is_some() && s.generation == (id >> SLOT_BITS) as u32 => Some(slot),
+        _ => None,
+    }
+}
+
+pub(crate) fn find_res(tex: &[TextureSlot], id: i32) -> Option<&Texture> {
+    find_slot(tex, id).and_then(|slot| tex[slot].tex.as_ref())
+}
+
+pub(crate) fn resolve<'a>(tex: &'a [TextureSlot], frame: &FrameTextures<'a>, id: i32) -> Option<TextureRef<'a>> {
+    if id < 0 {
+        frame.textures.get((-id - 1) as usize).map(|t| TextureRef::Borrowed(t))
+    } else {
+        find_res(tex, id).map(TextureRef::Registered)
+    }
+}
+
+fn check_size(data: &[u8], width: u32, height: u32, format: ImageFormat) -> Result<(), DrawerError> {
+    let expected = width as usize * height as usize * format.bytes_per_pixel();
+    if data.len() != expected {
+        return Err(DrawerError::ImageSize { expected: expected, actual: data.len() });
+    }
+    Ok(())
+}
+
+fn raw_image(data: &[u8], width: u32, height: u32, format: ImageFormat) -> RawImage2d<'_, u8> {
+    RawImage2d {
+        data: Cow::Borrowed(data),
+        width: width,
+        height: height,
+        format: format.client_format(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_generation_retires_slot() {
+        let mut slot = TextureSlot { tex: None, generation: GENERATION_MASK - 1 };
+        assert!(slot.release());
+        let id = slot_id(MAX_SLOTS - 1, slot.generation);
+        assert!(id > 0);
+        assert_eq!(((id & SLOT_MASK) - 1) as usize, MAX_SLOTS - 1);
+        assert_eq!((id >> SLOT_BITS) as u32, GENERATION_MASK);
+        assert!(!slot.release());
+        assert_eq!(slot.generation, GENERATION_MASK);
+    }
+}
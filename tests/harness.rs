@@ -0,0 +1,143 @@
+#![cfg(feature = "testing")]
+
+use nuklear::{nk_string, Context, Flags, PanelFlags, Rect, TextAlignment};
+use nuklear_backend_glium::testing::{assert_golden, Harness, HarnessError, Tolerance, SKIP_GL_TESTS_ENV};
+use nuklear_backend_glium::{ClipMode, Streaming};
+
+const FONT: &[u8] = include_bytes!("fonts/ProggyClean.ttf");
+
+// Rasterization differs slightly between GL drivers, the golden images are rendered by Mesa llvmpipe.
+const TOLERANCE: Tolerance = Tolerance { channel: 16, pixels: 64 };
+
+// A missing GL context fails the tests unless `NUKLEAR_SKIP_GL_TESTS` is set.
+fn harness(width: u32, height: u32) -> Option<Harness> {
+    match Harness::new(width, height, FONT, 13.0) {
+        Ok(harness) => Some(harness),
+        Err(e @ HarnessError::Egl(_)) | Err(e @ HarnessError::Context(_)) if std::env::var_os(SKIP_GL_TESTS_ENV).is_some() => {
+            eprintln!("skipping, no headless GL context: {}", e);
+            None
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn demo_window() {
+    let Some(mut harness) = harness(240, 160) else { return };
+    let image = harness
+        .frame(|ctx| {
+            if ctx.begin(nk_string!("Demo"), Rect { x: 10.0, y: 10.0, w: 220.0, h: 140.0 }, PanelFlags::Border as Flags | PanelFlags::Title as Flags) {
+                ctx.layout_row_dynamic(24.0, 1);
+                ctx.text("Hello glium", TextAlignment::Left as Flags);
+                ctx.button_text("Button");
+                ctx.progress(&mut 40, 100, false);
+            }
+            ctx.end();
+        })
+        .unwrap();
+    assert_golden(&image, concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/demo_window.png"), TOLERANCE);
+}
+
+#[test]
+fn empty_frame() {
+    let Some(mut harness) = harness(64, 64) else { return };
+    harness.set_clear_color((0.0, 0.0, 1.0, 1.0));
+    let image = harness.frame(|_| {}).unwrap();
+    assert!(image.pixels.chunks(4).all(|p| p == [0, 0, 255, 255]));
+    assert_eq!(harness.drawer().stats().draw_calls, 0);
+}
+
+#[test]
+fn gpu_timing_survives_empty_frame() {
+    let Some(mut harness) = harness(64, 64) else { return };
+    harness.drawer().set_gpu_timing(true);
+    harness.frame(|_| {}).unwrap();
+    let timed = (0..16).any(|_| {
+        harness
+            .frame(|ctx| {
+                if ctx.begin(nk_string!("Timed"), Rect { x: 0.0, y: 0.0, w: 64.0, h: 64.0 }, PanelFlags::Border as Flags) {
+                    ctx.layout_row_dynamic(24.0, 1);
+                    ctx.button_text("Button");
+                }
+                ctx.end();
+            })
+            .unwrap();
+        harness.drawer().stats().gpu_time.is_some()
+    });
+    assert!(timed);
+}
+
+#[test]
+fn unchanged_frame_is_not_uploaded() {
+    let Some(mut harness) = harness(64, 64) else { return };
+    let mut uploaded = |label: &str| {
+        harness
+            .frame(|ctx| {
+                if ctx.begin(nk_string!("Cached"), Rect { x: 0.0, y: 0.0, w: 64.0, h: 64.0 }, PanelFlags::Border as Flags) {
+                    ctx.layout_row_dynamic(24.0, 1);
+                    ctx.text(label, TextAlignment::Left as Flags);
+                }
+                ctx.end();
+            })
+            .unwrap();
+        harness.drawer().stats().bytes_uploaded
+    };
+    assert!(uploaded("same") > 0);
+    assert_eq!(uploaded("same"), 0);
+    assert!(uploaded("other") > 0);
+}
+
+// Two overlapping windows whose content runs past their bottom edge, so every command is clipped somewhere.
+fn overlapping_windows(ctx: &mut Context) {
+    for (name, x, y) in [(nk_string!("First"), 10.0, 10.0), (nk_string!("Second"), 120.0, 60.0)] {
+        if ctx.begin(name, Rect { x, y, w: 150.0, h: 120.0 }, PanelFlags::Border as Flags | PanelFlags::Title as Flags) {
+            ctx.layout_row_dynamic(30.0, 1);
+            for i in 0..10 {
+                ctx.button_text(&format!("Button {}", i));
+            }
+        }
+        ctx.end();
+    }
+}
+
+#[test]
+fn clip_modes_match_scissor() {
+    let Some(mut harness) = harness(300, 200) else { return };
+    let scissor = harness.frame(overlapping_windows).unwrap();
+    for mode in [ClipMode::Shader, ClipMode::Stencil] {
+        harness.drawer().set_clip_mode(mode);
+        let image = harness.frame(overlapping_windows).unwrap();
+        assert!(image == scissor, "{:?} clipping differs from Scissor", mode);
+    }
+}
+
+#[test]
+fn streaming_modes_match_orphan() {
+    // Every frame differs, so each one is uploaded into the next buffer of the ring.
+    let frames = |streaming| {
+        let mut harness = harness(200, 160)?;
+        harness.drawer().set_streaming(streaming);
+        let images = (0..6)
+            .map(|i| {
+                harness
+                    .frame(|ctx| {
+                        if ctx.begin(nk_string!("Stream"), Rect { x: 10.0, y: 10.0, w: 180.0, h: 140.0 }, PanelFlags::Border as Flags) {
+                            ctx.layout_row_dynamic(24.0, 1);
+                            ctx.text(&format!("Frame {}", i), TextAlignment::Left as Flags);
+                            ctx.progress(&mut (i * 20), 100, false);
+                            for j in 0..i % 3 {
+                                ctx.button_text(&format!("Button {}", j));
+                            }
+                        }
+                        ctx.end();
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        Some(images)
+    };
+    let Some(orphan) = frames(Streaming::Orphan) else { return };
+    for streaming in [Streaming::Ring(3), Streaming::Persistent(3)] {
+        assert!(frames(streaming).unwrap() == orphan, "{:?} frames differ from Orphan", streaming);
+    }
+}